@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::iter;
+use chrono::{Datelike, Days, Local, Month, Months, NaiveDate, Weekday};
+use colored::Colorize;
+use itertools::Itertools;
+use tabled::{builder::Builder, settings::style::Style, Table};
+use crate::{AppError, Column, SerdeDate, Todo};
+use crate::list::group_table;
+use crate::modify::series_anchor;
+use crate::recur::Recurrence;
+use crate::theme;
+
+const AGENDA_COLUMNS: [Column; 4] = [Column::Id, Column::Due, Column::Status, Column::Subject];
+
+/// The longest agenda, about ten years; also the most occurrences projected
+/// for one recurring todo, as no rule recurs more than daily.
+pub const MAX_DAYS: u64 = 3660;
+
+/// Open todos due by `end`, each paired with whether it is a projected
+/// occurrence of a recurring todo rather than the todo itself. Like
+/// completing a recurring todo, projecting skips dates up to `today`.
+pub fn occurrences(todos: &Vec<Todo>, today: NaiveDate, end: NaiveDate) -> Vec<(Todo, bool)> {
+    let mut found = Vec::new();
+    for todo in todos.iter().filter(|t| !t.completed && !t.archived) {
+        let Some(due) = todo.due.date() else { continue };
+        if due <= end {
+            found.push((todo.clone(), false));
+        }
+        let Ok(rule) = Recurrence::try_from(todo.recur.as_str()) else { continue };
+        let anchor = series_anchor(todos, todo);
+        let projected = iter::successors(Some(rule.next_due(Some(due), anchor, due.max(today))), |prev| Some(rule.next_due(Some(*prev), anchor, *prev)))
+            .take_while(|next| *next <= end && todo.recur_until.date().is_none_or(|until| *next <= until))
+            .take(MAX_DAYS as usize);
+        for next in projected {
+            found.push((Todo { due: SerdeDate::from(next).with_time(todo.due.time()), ..todo.clone() }, true));
+        }
+    }
+    found.sort_by(|(a, _), (b, _)| a.due.cmp(&b.due).then(b.is_priority.cmp(&a.is_priority)).then(a.id.cmp(&b.id)));
+    found
+}
+
+pub fn heading(date: NaiveDate, today: NaiveDate) -> String {
+    match (date - today).num_days() {
+        d if d < 0 => "Overdue".to_string(),
+        0 => "Today".to_string(),
+        1 => "Tomorrow".to_string(),
+        d if d < 7 => date.format("%A").to_string(),
+        _ => SerdeDate::from(date).to_string(),
+    }
+}
+
+/// A day's heading and its todos, each marked if it is projected.
+pub type AgendaDay = (String, Vec<(Todo, bool)>);
+
+/// The todos due in the `days` days from `today` on and the overdue ones,
+/// under a heading for each day. `days` must be between 1 and `MAX_DAYS`.
+pub fn agenda(todos: &Vec<Todo>, today: NaiveDate, days: u64) -> Result<Vec<AgendaDay>, AppError> {
+    if !(1..=MAX_DAYS).contains(&days) {
+        return Err(AppError::InvalidDaysError(days));
+    }
+    let end = today.checked_add_days(Days::new(days - 1)).ok_or(AppError::InvalidDaysError(days))?;
+    let groups = occurrences(todos, today, end).into_iter()
+        .chunk_by(|(todo, _)| todo.due.date().map(|d| heading(d, today)).unwrap_or_default())
+        .into_iter()
+        .map(|(heading, entries)| (heading, entries.collect()))
+        .collect();
+    Ok(groups)
+}
+
+pub fn print_agenda(todos: &Vec<Todo>, days: u64) -> Result<(), Box<dyn Error>> {
+    let groups = agenda(todos, Local::now().date_naive(), days)?;
+    if groups.is_empty() {
+        let when = if days == 1 { "today".to_string() } else { format!("in the next {} days", days) };
+        println!("Nothing due {}.", when);
+    }
+    for (heading, entries) in groups {
+        let shown: Vec<Todo> = entries.into_iter().map(|(todo, projected)| match projected {
+            true => Todo { subject: format!("{} {}", todo.subject, "(upcoming)".dimmed()), ..todo },
+            false => todo,
+        }).collect();
+        let rows: Vec<&Todo> = shown.iter().collect();
+        println!("{}:\n{}", heading, group_table(todos, &rows, false, &AGENDA_COLUMNS));
+    }
+    Ok(())
+}
+
+/// The first day of a month given as 2025-03, 3, mar or march, in the
+/// current year unless given; the current month without one.
+pub fn parse_month(value: Option<&str>, today: NaiveDate) -> Result<NaiveDate, AppError> {
+    let first = |year: i32, month: u32| NaiveDate::from_ymd_opt(year, month, 1);
+    let Some(value) = value else {
+        return Ok(today.with_day(1).unwrap());
+    };
+    let found = match value.trim().split_once('-') {
+        Some((y, m)) => y.parse().ok().zip(m.parse().ok()).and_then(|(y, m)| first(y, m)),
+        None => match value.trim().parse::<u32>() {
+            Ok(m) => first(today.year(), m),
+            Err(_) => value.trim().parse::<Month>().ok().and_then(|m| first(today.year(), m.number_from_month())),
+        },
+    };
+    found.ok_or(AppError::InvalidDateError(value.to_string()))
+}
+
+/// How many open todos are due on each day of the month starting on `first`,
+/// counting projected occurrences of recurring todos.
+pub fn due_counts(todos: &Vec<Todo>, first: NaiveDate, today: NaiveDate) -> BTreeMap<NaiveDate, usize> {
+    let end = first + Months::new(1) - Days::new(1);
+    let mut counts = BTreeMap::new();
+    for (todo, _) in occurrences(todos, today, end) {
+        if let Some(due) = todo.due.date().filter(|d| *d >= first) {
+            *counts.entry(due).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+fn day_cell(day: NaiveDate, counts: &BTreeMap<NaiveDate, usize>, today: NaiveDate) -> String {
+    let theme = theme::current();
+    let number = format!("{:>2}", day.day());
+    let number = if day == today { theme.today(&number) } else { number };
+    match counts.get(&day) {
+        Some(count) => format!("{} {}", number, theme.due(&format!("({})", count), day < today)),
+        None => number,
+    }
+}
+
+/// A grid of the month starting on `first`, weeks starting on Monday, with
+/// the number of todos due on each day.
+pub fn month_grid(first: NaiveDate, counts: &BTreeMap<NaiveDate, usize>, today: NaiveDate) -> Table {
+    let mut builder = Builder::default();
+    builder.push_record(iter::successors(Some(Weekday::Mon), |d| Some(d.succ())).take(7).map(|d| d.to_string()));
+    let days = first.iter_days().take_while(|d| d.month() == first.month()).map(|d| day_cell(d, counts, today));
+    let cells: Vec<String> = iter::repeat_n(String::new(), first.weekday().num_days_from_monday() as usize).chain(days).collect();
+    for week in cells.chunks(7) {
+        builder.push_record(week.to_vec());
+    }
+    let mut table = builder.build();
+    table.with(Style::blank());
+    table
+}
+
+pub fn print_cal(todos: &Vec<Todo>, month: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let today = Local::now().date_naive();
+    let first = parse_month(month, today)?;
+    println!("{}:\n{}", first.format("%B %Y"), month_grid(first, &due_counts(todos, first, today), today));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn gen_todo() -> Vec<Todo> {
+        let due = |d: NaiveDate| SerdeDate::from(d);
+        let mut todos = vec![
+            Todo::builder("water plants").id(0).due(due(date(2025, 1, 13))).recur("weekly").unwrap().build(),
+            Todo::builder("pay rent").id(1).due(due(date(2025, 1, 15))).build(),
+            Todo::builder("standup").id(2).due(due(date(2025, 1, 15))).recur("daily").unwrap().recur_until(due(date(2025, 1, 17))).build(),
+            Todo::builder("done").id(3).due(due(date(2025, 1, 15))).build(),
+            Todo::builder("someday").id(4).build(),
+            Todo::builder("urgent").id(5).due(due(date(2025, 1, 16))).priority(true).build(),
+        ];
+        todos[3].completed = true;
+        todos
+    }
+
+    fn ids(entries: &[(Todo, bool)]) -> Vec<(u64, bool)> {
+        entries.iter().map(|(t, projected)| (t.id, *projected)).collect()
+    }
+
+    #[test]
+    fn test_heading() {
+        let today = date(2025, 1, 15);
+
+        assert_eq!(heading(date(2025, 1, 2), today), "Overdue");
+        assert_eq!(heading(today, today), "Today");
+        assert_eq!(heading(date(2025, 1, 16), today), "Tomorrow");
+        assert_eq!(heading(date(2025, 1, 21), today), "Tuesday");
+        assert_eq!(heading(date(2025, 1, 22), today), SerdeDate::from(date(2025, 1, 22)).to_string());
+    }
+
+    #[test]
+    fn test_agenda() {
+        let groups = agenda(&gen_todo(), date(2025, 1, 15), 7).unwrap();
+
+        let headings: Vec<&str> = groups.iter().map(|(h, _)| h.as_str()).collect();
+        assert_eq!(headings, ["Overdue", "Today", "Tomorrow", "Friday", "Monday"]);
+        assert_eq!(ids(&groups[0].1), [(0, false)]);
+        assert_eq!(ids(&groups[1].1), [(1, false), (2, false)]);
+        assert_eq!(ids(&groups[2].1), [(5, false), (2, true)]);
+        assert_eq!(ids(&groups[3].1), [(2, true)]);
+        assert_eq!(ids(&groups[4].1), [(0, true)]);
+        assert_eq!(groups[4].1[0].0.due, SerdeDate::from(date(2025, 1, 20)));
+    }
+
+    #[test]
+    fn test_agenda_days() {
+        let today = date(2025, 1, 15);
+
+        assert_eq!(agenda(&gen_todo(), today, 0), Err(AppError::InvalidDaysError(0)));
+        assert_eq!(agenda(&gen_todo(), today, u64::MAX), Err(AppError::InvalidDaysError(u64::MAX)));
+        assert_eq!(agenda(&gen_todo(), NaiveDate::MAX, 2), Err(AppError::InvalidDaysError(2)));
+
+        let groups = agenda(&gen_todo(), today, 1).unwrap();
+        assert_eq!(groups.iter().map(|(h, _)| h.as_str()).collect::<Vec<&str>>(), ["Overdue", "Today"]);
+        let groups = agenda(&gen_todo(), today, MAX_DAYS).unwrap();
+        let last = groups.last().unwrap().1.last().unwrap().0.due.date().unwrap();
+        assert!(last <= today + Days::new(MAX_DAYS - 1) && last > today + Days::new(MAX_DAYS - 8));
+    }
+
+    #[test]
+    fn test_occurrences_bounded() {
+        let todos = vec![Todo::builder("stretch").id(0).due(SerdeDate::from(date(2025, 1, 15))).recur("daily").unwrap().build()];
+
+        let found = occurrences(&todos, date(2025, 1, 15), NaiveDate::MAX);
+
+        assert_eq!(found.len(), 1 + MAX_DAYS as usize);
+    }
+
+    #[test]
+    fn test_parse_month() {
+        let today = date(2025, 1, 15);
+
+        assert_eq!(parse_month(None, today), Ok(date(2025, 1, 1)));
+        assert_eq!(parse_month(Some("2024-11"), today), Ok(date(2024, 11, 1)));
+        assert_eq!(parse_month(Some("3"), today), Ok(date(2025, 3, 1)));
+        assert_eq!(parse_month(Some("Mar"), today), Ok(date(2025, 3, 1)));
+        assert_eq!(parse_month(Some("september"), today), Ok(date(2025, 9, 1)));
+        assert!(parse_month(Some("13"), today).is_err());
+        assert!(parse_month(Some("smarch"), today).is_err());
+    }
+
+    #[test]
+    fn test_month_grid() {
+        let today = date(2025, 1, 15);
+        let counts = due_counts(&gen_todo(), date(2025, 1, 1), today);
+
+        assert_eq!(counts.iter().map(|(d, c)| (d.day(), *c)).collect::<Vec<(u32, usize)>>(), [(13, 1), (15, 2), (16, 2), (17, 1), (20, 1), (27, 1)]);
+
+        let grid = month_grid(date(2025, 1, 1), &counts, today).to_string();
+        let lines: Vec<&str> = grid.lines().map(|l| l.trim_end()).collect();
+        assert_eq!(lines[0].split_whitespace().collect::<Vec<&str>>(), ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"]);
+        assert_eq!(lines[1].split_whitespace().collect::<Vec<&str>>(), ["1", "2", "3", "4", "5"]);
+        assert_eq!(lines[3].split_whitespace().collect::<Vec<&str>>(), ["13", "(1)", "14", "15", "(2)", "16", "(2)", "17", "(1)", "18", "19"]);
+        assert_eq!(lines.len(), 6);
+    }
+}
@@ -0,0 +1,304 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::fs::{canonicalize, read_to_string};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use chrono::NaiveDate;
+use toml::{Table, Value};
+use crate::{AppError, ColorChoice, GroupOption};
+use crate::theme;
+use crate::todo_files::write_atomic;
+
+/// Every setting with its default. Each can also be set for a single
+/// todolist in a `[todolists.<name>]` table.
+pub const KEYS: [(&str, &str); 7] = [
+    ("auto_commit", "false"),
+    ("color", "auto"),
+    ("completed_status", "completed"),
+    ("date_format", "%a %b %d"),
+    ("group", "none"),
+    ("sync_endpoint", ""),
+    ("theme", "default"),
+];
+
+fn invalid(message: String) -> AppError {
+    AppError::InvalidConfigError(message)
+}
+
+fn unknown_key(key: &str) -> AppError {
+    let known: Vec<&str> = KEYS.iter().map(|(k, _)| *k).collect();
+    invalid(format!("unknown key \"{}\"; expected one of {}", key, known.join(", ")))
+}
+
+/// Whether `format` can render a date; time fields like %H cannot, and
+/// would only fail once a date is shown.
+fn formats_dates(format: &str) -> bool {
+    let mut text = String::new();
+    !format.is_empty() && write!(text, "{}", NaiveDate::default().format(format)).is_ok()
+}
+
+/// Checks a value given as text and turns it into the type stored in the file.
+fn parse_value(key: &str, value: &str) -> Result<Value, AppError> {
+    let bad = |expected: &str| invalid(format!("invalid value \"{}\" for {}: expected {}", value, key, expected));
+    match key {
+        "auto_commit" => value.parse::<bool>().map(Value::Boolean).map_err(|_| bad("true or false")),
+        "color" if !["auto", "always", "never"].contains(&value) => Err(bad("auto, always or never")),
+        "theme" if value.is_empty() => Err(bad("a theme name")),
+        "group" if !["none", "project", "context", "status"].contains(&value) => Err(bad("none, project, context or status")),
+        "date_format" if !formats_dates(value) => Err(bad("a strftime date format like %a %b %d")),
+        "completed_status" if value.is_empty() => Err(bad("a non-empty status")),
+        _ if KEYS.iter().any(|(k, _)| *k == key) => Ok(Value::String(value.to_string())),
+        _ => Err(unknown_key(key)),
+    }
+}
+
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+fn check_table(table: &Table, context: &str) -> Result<(), AppError> {
+    for (key, value) in table {
+        parse_value(key, &value_text(value)).map_err(|e| match e {
+            AppError::InvalidConfigError(m) => invalid(format!("{}: {}", context, m)),
+            e => e,
+        })?;
+        let right_type = if key == "auto_commit" { value.is_bool() } else { value.is_str() };
+        if !right_type {
+            return Err(invalid(format!("{}: {} has the wrong type", context, key)));
+        }
+    }
+    Ok(())
+}
+
+/// Where a setting's value came from, for `tlist config list`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    Global,
+    Todolist,
+}
+
+/// `config.toml` in the config dir.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    path: PathBuf,
+    table: Table,
+}
+
+impl Config {
+    /// A missing file is an empty config. Unknown keys and bad values are
+    /// reported with the table they are in.
+    pub fn load(confdir: &Path) -> Result<Config, Box<dyn Error>> {
+        let config = Config::load_unchecked(confdir)?;
+        config.check()?;
+        Ok(config)
+    }
+
+    /// Like `load`, but keeps unknown keys and bad values, so that
+    /// `tlist config` can still show and fix them.
+    pub fn load_unchecked(confdir: &Path) -> Result<Config, Box<dyn Error>> {
+        let path = confdir.join("config.toml");
+        if !path.exists() {
+            return Ok(Config { path, table: Table::new() });
+        }
+        let table: Table = read_to_string(&path)?.parse().map_err(|e| invalid(format!("{}", e).trim().to_string()))?;
+        Ok(Config { path, table })
+    }
+
+    fn check(&self) -> Result<(), AppError> {
+        for (key, value) in &self.table {
+            match (key.as_str(), value) {
+                ("todolists", Value::Table(lists)) => {
+                    for (name, settings) in lists {
+                        let Value::Table(settings) = settings else {
+                            return Err(invalid(format!("todolists.{} must be a table", name)));
+                        };
+                        check_table(settings, &format!("[todolists.{}]", name))?;
+                    }
+                },
+                ("todolists", _) => return Err(invalid("todolists must be a table".to_string())),
+                _ => check_table(&Table::from_iter([(key.clone(), value.clone())]), "config.toml")?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes config.toml atomically, through a symlink if it is one.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+        write_atomic(&path, &toml::to_string(&self.table)?)
+    }
+
+    fn todolist_table(&self, todolist: &str) -> Option<&Table> {
+        self.table.get("todolists")?.as_table()?.get(todolist)?.as_table()
+    }
+
+    /// The value of `key` for `todolist`, falling back to the global value
+    /// and then the default.
+    pub fn get(&self, key: &str, todolist: Option<&str>) -> Result<(String, Source), AppError> {
+        let default = KEYS.iter().find(|(k, _)| *k == key).ok_or_else(|| unknown_key(key))?.1;
+        if let Some(value) = todolist.and_then(|t| self.todolist_table(t)).and_then(|t| t.get(key)) {
+            return Ok((value_text(value), Source::Todolist));
+        }
+        match self.table.get(key) {
+            Some(value) => Ok((value_text(value), Source::Global)),
+            None => Ok((default.to_string(), Source::Default)),
+        }
+    }
+
+    /// Whether `key` is set for `todolist` itself rather than inherited.
+    pub fn is_set(&self, key: &str, todolist: &str) -> bool {
+        self.todolist_table(todolist).is_some_and(|t| t.contains_key(key))
+    }
+
+    /// The directory the config file is in, which also holds the themes.
+    pub fn dir(&self) -> &Path {
+        self.path.parent().unwrap_or(Path::new(""))
+    }
+
+    pub fn set(&mut self, key: &str, value: &str, todolist: Option<&str>) -> Result<(), AppError> {
+        if key == "theme" {
+            theme::exists(self.dir(), value)?;
+        }
+        let value = parse_value(key, value)?;
+        let table = match todolist {
+            None => &mut self.table,
+            Some(name) => {
+                let lists = self.table.entry("todolists").or_insert(Value::Table(Table::new()));
+                let list = lists.as_table_mut().ok_or_else(|| invalid("todolists must be a table".to_string()))?
+                    .entry(name).or_insert(Value::Table(Table::new()));
+                list.as_table_mut().ok_or_else(|| invalid(format!("todolists.{} must be a table", name)))?
+            },
+        };
+        table.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    pub fn list(&self, todolist: Option<&str>) -> Vec<(&'static str, String, Source)> {
+        KEYS.iter()
+            .filter_map(|(key, _)| self.get(key, todolist).ok().map(|(value, source)| (*key, value, source)))
+            .collect()
+    }
+
+    pub fn settings(&self, todolist: Option<&str>) -> Settings {
+        let get = |key| self.get(key, todolist).map(|(v, _)| v).unwrap_or_default();
+        Settings {
+            auto_commit: get("auto_commit") == "true",
+            color: match get("color").as_str() {
+                "always" => ColorChoice::Always,
+                "never" => ColorChoice::Never,
+                _ => ColorChoice::Auto,
+            },
+            completed_status: get("completed_status"),
+            date_format: get("date_format"),
+            group: match get("group").as_str() {
+                "project" => Some(GroupOption::Project),
+                "context" => Some(GroupOption::Context),
+                "status" => Some(GroupOption::Status),
+                _ => None,
+            },
+            sync_endpoint: Some(get("sync_endpoint")).filter(|e| !e.is_empty()),
+            theme: get("theme"),
+        }
+    }
+}
+
+/// The settings in effect for one todolist.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub auto_commit: bool,
+    pub color: ColorChoice,
+    pub completed_status: String,
+    pub date_format: String,
+    pub group: Option<GroupOption>,
+    pub sync_endpoint: Option<String>,
+    pub theme: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Config::default().settings(None)
+    }
+}
+
+static CURRENT: OnceLock<Settings> = OnceLock::new();
+
+/// Makes `settings` the ones used for display and new completions; done
+/// once at startup, for the active todolist.
+pub fn init(settings: Settings) {
+    let _ = CURRENT.set(settings);
+}
+
+pub fn current() -> &'static Settings {
+    CURRENT.get_or_init(Settings::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use uuid::Uuid;
+
+    fn gen_confdir(contents: &str) -> PathBuf {
+        let dir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4()));
+        create_dir_all(&dir).unwrap();
+        write(dir.join("config.toml"), contents).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_overrides() {
+        let dir = gen_confdir("group = \"project\"\n\n[todolists.work]\ngroup = \"context\"\nauto_commit = true\n");
+        let config = Config::load(&dir).unwrap();
+
+        assert_eq!(config.get("group", Some("work")), Ok(("context".to_string(), Source::Todolist)));
+        assert_eq!(config.get("group", Some("home")), Ok(("project".to_string(), Source::Global)));
+        assert_eq!(config.get("date_format", None), Ok(("%a %b %d".to_string(), Source::Default)));
+        assert!(config.settings(Some("work")).auto_commit);
+        assert!(!config.settings(None).auto_commit);
+        assert_eq!(config.settings(None).color, ColorChoice::Auto);
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_set_and_save() {
+        let dir = gen_confdir("");
+        let mut config = Config::load(&dir).unwrap();
+
+        assert!(config.set("date_format", "%d.%m.", None).is_ok());
+        assert!(config.set("auto_commit", "true", Some("work")).is_ok());
+        config.save().unwrap();
+
+        let saved = read_to_string(dir.join("config.toml")).unwrap();
+        assert_eq!(saved, "date_format = \"%d.%m.\"\n\n[todolists.work]\nauto_commit = true\n");
+        assert_eq!(Config::load(&dir).unwrap(), config);
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid() {
+        let mut config = Config::default();
+
+        assert!(matches!(config.set("colour", "true", None), Err(AppError::InvalidConfigError(m)) if m.starts_with("unknown key \"colour\"")));
+        assert!(matches!(config.set("auto_commit", "yes", None), Err(AppError::InvalidConfigError(_))));
+        assert!(matches!(config.set("group", "tag", None), Err(AppError::InvalidConfigError(_))));
+        assert!(matches!(config.set("date_format", "%Q", None), Err(AppError::InvalidConfigError(_))));
+        assert!(matches!(config.set("date_format", "%H:%M", None), Err(AppError::InvalidConfigError(_))));
+        assert!(config.set("date_format", "%Y-%m-%d (%A)", None).is_ok());
+        assert!(matches!(config.set("color", "true", None), Err(AppError::InvalidConfigError(_))));
+        assert!(matches!(config.set("theme", "nope", None), Err(AppError::InvalidConfigError(m)) if m.starts_with("unknown theme \"nope\"")));
+        assert!(config.set("theme", "mono", None).is_ok());
+        assert!(config.get("colour", None).is_err());
+
+        for contents in ["colour = true", "[todolists.work]\ncolour = true", "auto_commit = \"true\"", "group = "] {
+            let dir = gen_confdir(contents);
+            assert!(Config::load(&dir).is_err(), "{}", contents);
+            assert_eq!(Config::load_unchecked(&dir).is_ok(), contents != "group = ", "{}", contents);
+            remove_dir_all(dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use chrono::{Datelike, Days, Local, NaiveDate, NaiveDateTime};
+use crate::{Todo, AppError};
+use crate::serde_date::SerdeDate;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct ListMatch {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ListMatch {
+    fn parse(value: &str) -> ListMatch {
+        let mut list_match = ListMatch::default();
+        value.split(',').filter(|v| !v.is_empty()).for_each(|v| {
+            match v.strip_prefix('-') {
+                Some(excluded) => list_match.exclude.push(excluded.to_lowercase()),
+                None => list_match.include.push(v.to_lowercase()),
+            }
+        });
+        list_match
+    }
+
+    fn matches<'a>(&self, values: impl Iterator<Item=&'a str> + Clone) -> bool {
+        let has = |wanted: &String| values.clone().any(|v| v.to_lowercase() == *wanted);
+        (self.include.is_empty() || self.include.iter().any(has)) && !self.exclude.iter().any(has)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    DueBetween(NaiveDate, NaiveDate),
+    DueBefore(NaiveDate),
+    DueAfter(NaiveDate),
+    /// Overdue as of this moment, the same way the due column is shown
+    Overdue(NaiveDateTime),
+    NoDue,
+    Projects(ListMatch),
+    Contexts(ListMatch),
+    Status(ListMatch),
+    Priority(bool),
+    Completed(bool),
+    Archived(bool),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    terms: Vec<Term>,
+}
+
+fn week_start(date: NaiveDate) -> NaiveDate {
+    date - Days::new(date.weekday().num_days_from_monday().into())
+}
+
+fn invalid(term: &str, reason: &str) -> AppError {
+    AppError::InvalidFilterError(term.to_string(), reason.to_string())
+}
+
+fn parse_date(term: &str, value: &str) -> Result<NaiveDate, AppError> {
+    SerdeDate::try_from(Some(value.to_string())).ok()
+        .and_then(|d| d.date())
+        .ok_or(invalid(term, "expected a date like today, tom, mon, nov28 or 2025-01-31"))
+}
+
+fn parse_bool(term: &str, value: &str) -> Result<bool, AppError> {
+    match value {
+        "true" | "yes" => Ok(true),
+        "false" | "no" => Ok(false),
+        _ => Err(invalid(term, "expected true or false")),
+    }
+}
+
+fn parse_due(term: &str, value: &str, now: NaiveDateTime) -> Result<Term, AppError> {
+    let today = now.date();
+    let this_week = week_start(today);
+    Ok(match value {
+        "today" | "tod" => Term::DueBetween(today, today),
+        "tomorrow" | "tom" => Term::DueBetween(today + Days::new(1), today + Days::new(1)),
+        "yesterday" => Term::DueBetween(today - Days::new(1), today - Days::new(1)),
+        "this_week" => Term::DueBetween(this_week, this_week + Days::new(6)),
+        "next_week" => Term::DueBetween(this_week + Days::new(7), this_week + Days::new(13)),
+        "last_week" => Term::DueBetween(this_week - Days::new(7), this_week - Days::new(1)),
+        "overdue" => Term::Overdue(now),
+        "none" => Term::NoDue,
+        _ => {
+            let date = parse_date(term, value)?;
+            Term::DueBetween(date, date)
+        }
+    })
+}
+
+const KEYS: [&str; 12] = ["due", "duebefore", "dueafter", "project", "projects", "context", "contexts", "status", "is", "not", "completed", "archived"];
+
+/// A word whose part before ':' is not a filter key, like "10:30" or
+/// "http://example.com", is matched against the subject like any other word.
+fn parse_term(term: &str, now: NaiveDateTime) -> Result<Term, AppError> {
+    let Some((key, value)) = term.split_once(':').filter(|(key, _)| KEYS.contains(key)) else {
+        return Ok(Term::Text(term.to_lowercase()));
+    };
+    if value.is_empty() {
+        return Err(invalid(term, "missing a value after ':'"));
+    }
+    match key {
+        "due" => parse_due(term, value, now),
+        "duebefore" => Ok(Term::DueBefore(parse_date(term, value)?)),
+        "dueafter" => Ok(Term::DueAfter(parse_date(term, value)?)),
+        "project" | "projects" => Ok(Term::Projects(ListMatch::parse(value))),
+        "context" | "contexts" => Ok(Term::Contexts(ListMatch::parse(value))),
+        "status" => Ok(Term::Status(ListMatch::parse(value))),
+        "is" | "not" => match value {
+            "priority" | "prioritized" => Ok(Term::Priority(key == "is")),
+            "completed" => Ok(Term::Completed(key == "is")),
+            "archived" => Ok(Term::Archived(key == "is")),
+            _ => Err(invalid(term, "expected priority, completed or archived")),
+        },
+        "completed" => Ok(Term::Completed(parse_bool(term, value)?)),
+        "archived" => Ok(Term::Archived(parse_bool(term, value)?)),
+        _ => Ok(Term::Text(term.to_lowercase())),
+    }
+}
+
+impl Term {
+    fn matches(&self, todo: &Todo) -> bool {
+        let due = todo.due.date();
+        match self {
+            Term::DueBetween(from, to) => due.is_some_and(|d| *from <= d && d <= *to),
+            Term::DueBefore(date) => due.is_some_and(|d| d < *date),
+            Term::DueAfter(date) => due.is_some_and(|d| d > *date),
+            Term::Overdue(now) => todo.due.is_overdue_at(*now),
+            Term::NoDue => due.is_none(),
+            Term::Projects(m) => m.matches(todo.projects.iter().map(|p| p.as_str())),
+            Term::Contexts(m) => m.matches(todo.contexts.iter().map(|c| c.as_str())),
+            Term::Status(m) => m.matches(Some(todo.status.as_str()).filter(|s| !s.is_empty()).into_iter()),
+            Term::Priority(set) => todo.is_priority == *set,
+            Term::Completed(set) => todo.completed == *set,
+            Term::Archived(set) => todo.archived == *set,
+            Term::Text(word) => todo.subject.to_lowercase().contains(word.as_str()),
+        }
+    }
+}
+
+impl Filter {
+    pub fn parse(terms: &[String]) -> Result<Filter, AppError> {
+        Filter::parse_at(terms, Local::now().naive_local())
+    }
+
+    /// Archived todos are left out unless a term asks about them; this is
+    /// the only place they are hidden from listings.
+    pub fn parse_at(terms: &[String], now: NaiveDateTime) -> Result<Filter, AppError> {
+        let mut terms = terms.iter()
+                             .flat_map(|t| t.split_whitespace())
+                             .map(|t| parse_term(t, now))
+                             .collect::<Result<Vec<Term>, AppError>>()?;
+        if !terms.iter().any(|t| matches!(t, Term::Archived(_))) {
+            terms.push(Term::Archived(false));
+        }
+        Ok(Filter { terms })
+    }
+
+    pub fn matches(&self, todo: &Todo) -> bool {
+        self.terms.iter().all(|t| t.matches(todo))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Todo, SerdeDate, SerdeDateTime, SerdeDuration};
+
+    fn today() -> NaiveDate {
+        // a wednesday
+        NaiveDate::from_ymd_opt(2025, 1, 15).unwrap()
+    }
+
+    fn now() -> NaiveDateTime {
+        today().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn gen_todo(due: Option<NaiveDate>) -> Todo {
+        Todo {
+            id: 0,
+            uuid: "".to_string(),
+            subject: "Buy milk +groceries @town".to_string(),
+            projects: vec!["groceries".to_string()],
+            contexts: vec!["town".to_string()],
+            due: due.map(SerdeDate::from).unwrap_or(SerdeDate::new_empty()),
+            completed: false,
+            completed_date: SerdeDateTime::new_empty(),
+            status: "".to_string(),
+            archived: false,
+            is_priority: false,
+            notes: None,
+            recur: "".to_string(),
+            recur_until: SerdeDate::new_empty(),
+            prev_recur_todo_uuid: "".to_string(),
+            parent_uuid: "".to_string(),
+            blocked_by: vec![],
+            time_entries: vec![],
+            estimate: SerdeDuration::new_empty(),
+            remind: SerdeDuration::new_empty(),
+            created: SerdeDateTime::new_empty()
+        }
+    }
+
+    fn filter(terms: &str) -> Filter {
+        let terms: Vec<String> = terms.split_whitespace().map(|t| t.to_string()).collect();
+        let filter = Filter::parse_at(&terms, now());
+        assert!(filter.is_ok(), "{:?}", filter);
+        filter.unwrap()
+    }
+
+    fn in_days(n: i64) -> Option<NaiveDate> {
+        Some(today() + chrono::Duration::days(n))
+    }
+
+    #[test]
+    fn test_empty_filter_hides_archived() {
+        let mut todo = gen_todo(None);
+        assert!(filter("").matches(&todo));
+        todo.archived = true;
+        assert!(!filter("").matches(&todo));
+    }
+
+    #[test]
+    fn test_due_today() {
+        assert!(filter("due:today").matches(&gen_todo(in_days(0))));
+        assert!(filter("due:tod").matches(&gen_todo(in_days(0))));
+        assert!(!filter("due:today").matches(&gen_todo(in_days(1))));
+        assert!(!filter("due:today").matches(&gen_todo(None)));
+    }
+
+    #[test]
+    fn test_due_tomorrow() {
+        assert!(filter("due:tom").matches(&gen_todo(in_days(1))));
+        assert!(!filter("due:tomorrow").matches(&gen_todo(in_days(0))));
+    }
+
+    #[test]
+    fn test_due_this_week() {
+        assert!(filter("due:this_week").matches(&gen_todo(in_days(-2))));
+        assert!(filter("due:this_week").matches(&gen_todo(in_days(4))));
+        assert!(!filter("due:this_week").matches(&gen_todo(in_days(5))));
+        assert!(filter("due:next_week").matches(&gen_todo(in_days(5))));
+        assert!(filter("due:last_week").matches(&gen_todo(in_days(-3))));
+    }
+
+    #[test]
+    fn test_due_overdue() {
+        assert!(filter("due:overdue").matches(&gen_todo(in_days(-1))));
+        assert!(!filter("due:overdue").matches(&gen_todo(in_days(0))));
+        assert!(!filter("due:overdue").matches(&gen_todo(None)));
+
+        let at = |h| Todo { due: SerdeDate::from(today()).with_time(chrono::NaiveTime::from_hms_opt(h, 0, 0)), ..gen_todo(None) };
+        assert!(filter("due:overdue").matches(&at(9)));
+        assert!(!filter("due:overdue").matches(&at(15)));
+    }
+
+    #[test]
+    fn test_due_none() {
+        assert!(filter("due:none").matches(&gen_todo(None)));
+        assert!(!filter("due:none").matches(&gen_todo(in_days(0))));
+    }
+
+    #[test]
+    fn test_due_before_after() {
+        assert!(filter("duebefore:2025-01-20").matches(&gen_todo(in_days(0))));
+        assert!(!filter("duebefore:2025-01-15").matches(&gen_todo(in_days(0))));
+        assert!(filter("dueafter:2025-01-14").matches(&gen_todo(in_days(0))));
+        assert!(!filter("dueafter:2025-01-15").matches(&gen_todo(in_days(0))));
+    }
+
+    #[test]
+    fn test_project() {
+        let todo = gen_todo(None);
+        assert!(filter("project:groceries").matches(&todo));
+        assert!(filter("project:work,groceries").matches(&todo));
+        assert!(!filter("project:work").matches(&todo));
+        assert!(!filter("project:-groceries").matches(&todo));
+        assert!(filter("project:-work").matches(&todo));
+    }
+
+    #[test]
+    fn test_context() {
+        let todo = gen_todo(None);
+        assert!(filter("context:town").matches(&todo));
+        assert!(!filter("context:home").matches(&todo));
+        assert!(!filter("context:town,-town").matches(&todo));
+    }
+
+    #[test]
+    fn test_status() {
+        let mut todo = gen_todo(None);
+        assert!(!filter("status:waiting").matches(&todo));
+        assert!(filter("status:-waiting").matches(&todo));
+        todo.status = "waiting".to_string();
+        assert!(filter("status:waiting,next").matches(&todo));
+        assert!(!filter("status:-waiting").matches(&todo));
+    }
+
+    #[test]
+    fn test_is_priority() {
+        let mut todo = gen_todo(None);
+        assert!(!filter("is:priority").matches(&todo));
+        assert!(filter("not:priority").matches(&todo));
+        todo.is_priority = true;
+        assert!(filter("is:priority").matches(&todo));
+    }
+
+    #[test]
+    fn test_completed() {
+        let mut todo = gen_todo(None);
+        assert!(filter("completed:false").matches(&todo));
+        assert!(!filter("completed:true").matches(&todo));
+        todo.completed = true;
+        assert!(filter("completed:true").matches(&todo));
+    }
+
+    #[test]
+    fn test_archived() {
+        let mut todo = gen_todo(None);
+        todo.archived = true;
+        assert!(filter("archived:true").matches(&todo));
+        assert!(!filter("archived:false").matches(&todo));
+    }
+
+    #[test]
+    fn test_text() {
+        let todo = gen_todo(None);
+        assert!(filter("milk").matches(&todo));
+        assert!(filter("buy MILK").matches(&todo));
+        assert!(!filter("milk bread").matches(&todo));
+    }
+
+    #[test]
+    fn test_combined() {
+        let mut todo = gen_todo(in_days(0));
+        todo.is_priority = true;
+        assert!(filter("due:today is:priority project:groceries milk").matches(&todo));
+        assert!(!filter("due:today is:priority project:groceries bread").matches(&todo));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_text() {
+        let mut todo = gen_todo(None);
+        todo.subject = "Call at 10:30 about http://example.com".to_string();
+        assert!(filter("10:30").matches(&todo));
+        assert!(filter("http://example.com").matches(&todo));
+        assert!(!filter("colour:red").matches(&todo));
+        assert!(!filter("re:").matches(&todo));
+    }
+
+    #[test]
+    fn test_invalid_terms() {
+        let parse = |t: &str| Filter::parse_at(&[t.to_string()], now());
+        assert!(matches!(parse("due:someday"), Err(AppError::InvalidFilterError(t, _)) if t == "due:someday"));
+        assert!(matches!(parse("completed:maybe"), Err(AppError::InvalidFilterError(t, _)) if t == "completed:maybe"));
+        assert!(matches!(parse("is:blue"), Err(AppError::InvalidFilterError(t, _)) if t == "is:blue"));
+        assert!(matches!(parse("project:"), Err(AppError::InvalidFilterError(t, _)) if t == "project:"));
+    }
+}
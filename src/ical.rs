@@ -0,0 +1,486 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Utc, Weekday};
+use itertools::Itertools;
+use crate::{Todo, AppError, SerdeDate, SerdeDateTime, config};
+use crate::recur::Recurrence;
+use crate::modify::{find_new_id, get_contexts_and_projects};
+
+const DATE_FORMAT: &str = "%Y%m%d";
+const DATE_TIME_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const FOLD_WIDTH: usize = 75;
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') | Some('N') => out.push('\n'),
+                Some(other) => out.push(other),
+                None => (),
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Splits content lines longer than 75 octets, as RFC 5545 requires.
+fn fold(line: &str) -> String {
+    let mut out = String::new();
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > FOLD_WIDTH {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+    out
+}
+
+fn weekday_code(day: &Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+fn rrule(todo: &Todo) -> Result<String, AppError> {
+    let mut rule = match Recurrence::try_from(todo.recur.as_str())? {
+        Recurrence::Daily => "FREQ=DAILY".to_string(),
+        Recurrence::Weekdays => "FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR".to_string(),
+        Recurrence::Weekly => "FREQ=WEEKLY".to_string(),
+        Recurrence::Monthly => "FREQ=MONTHLY".to_string(),
+        Recurrence::Yearly => "FREQ=YEARLY".to_string(),
+        Recurrence::EveryNDays(n) => format!("FREQ=DAILY;INTERVAL={}", n),
+        Recurrence::OnWeekdays(days) => format!("FREQ=WEEKLY;BYDAY={}", days.iter().map(weekday_code).join(",")),
+    };
+    if let Some(until) = todo.recur_until.date() {
+        rule.push_str(&format!(";UNTIL={}", until.format(DATE_FORMAT)));
+    }
+    Ok(rule)
+}
+
+fn parse_rrule(rule: &str) -> Result<(String, SerdeDate), AppError> {
+    let invalid = || AppError::InvalidRecurrenceError(rule.to_string());
+    let mut freq = "";
+    let mut interval: u64 = 1;
+    let mut byday: Vec<&str> = Vec::new();
+    let mut until = SerdeDate::new_empty();
+    for part in rule.split(';') {
+        match part.split_once('=') {
+            Some(("FREQ", f)) => freq = f,
+            Some(("INTERVAL", n)) => interval = n.parse().map_err(|_| invalid())?,
+            Some(("BYDAY", days)) => byday = days.split(',').collect(),
+            Some(("UNTIL", u)) => until = parse_date(u).map(SerdeDate::from).ok_or_else(invalid)?,
+            Some(("WKST", _)) => (),
+            _ => return Err(invalid()),
+        }
+    }
+    let recur = match (freq, interval, byday.as_slice()) {
+        ("DAILY", n, []) => Recurrence::try_from(format!("every {} days", n).as_str())?,
+        ("WEEKLY", 1, ["MO", "TU", "WE", "TH", "FR"]) => Recurrence::Weekdays,
+        ("WEEKLY", 1, []) => Recurrence::Weekly,
+        ("WEEKLY", n, []) => Recurrence::try_from(format!("every {} days", n.checked_mul(7).ok_or_else(invalid)?).as_str())?,
+        ("WEEKLY", 1, days) => {
+            let names: Vec<&str> = days.iter().map(|d| match *d {
+                "MO" => Ok("mon"), "TU" => Ok("tue"), "WE" => Ok("wed"), "TH" => Ok("thu"),
+                "FR" => Ok("fri"), "SA" => Ok("sat"), "SU" => Ok("sun"), _ => Err(invalid()),
+            }).collect::<Result<Vec<&str>, AppError>>()?;
+            Recurrence::try_from(format!("every {}", names.join(",")).as_str())?
+        },
+        ("MONTHLY", 1, []) => Recurrence::Monthly,
+        ("YEARLY", 1, []) => Recurrence::Yearly,
+        _ => return Err(invalid()),
+    };
+    Ok((recur.to_string(), until))
+}
+
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value.get(..8).unwrap_or(value), DATE_FORMAT).ok()
+}
+
+fn parse_date_time(value: &str) -> Option<DateTime<Local>> {
+    if let Some(utc) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()
+            .map(|d| d.and_utc().with_timezone(&Local));
+    }
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+        .and_then(|d| d.and_local_timezone(Local).single())
+}
+
+/// A DUE with a time is kept as local wall-clock time.
+fn parse_due(value: &str) -> Option<SerdeDate> {
+    if value.contains('T') {
+        let due = parse_date_time(value)?.naive_local();
+        return Some(SerdeDate::from(due.date()).with_time(Some(due.time())));
+    }
+    parse_date(value).map(SerdeDate::from)
+}
+
+fn vtodo(todo: &Todo, stamp: &str) -> Result<String, AppError> {
+    let mut lines = vec![
+        "BEGIN:VTODO".to_string(),
+        format!("UID:{}", todo.uuid),
+        format!("DTSTAMP:{}", stamp),
+        format!("SUMMARY:{}", escape(&todo.subject)),
+    ];
+    if let Some(created) = todo.created.date() {
+        lines.push(format!("CREATED:{}", created.with_timezone(&Utc).format(DATE_TIME_FORMAT)));
+    }
+    match (todo.due.date(), todo.due.time()) {
+        (Some(due), Some(time)) => lines.push(format!("DUE:{}", due.and_time(time).format("%Y%m%dT%H%M%S"))),
+        (Some(due), None) => lines.push(format!("DUE;VALUE=DATE:{}", due.format(DATE_FORMAT))),
+        _ => (),
+    }
+    if todo.completed {
+        lines.push("STATUS:COMPLETED".to_string());
+        if let Some(date) = todo.completed_date.date() {
+            lines.push(format!("COMPLETED:{}", date.with_timezone(&Utc).format(DATE_TIME_FORMAT)));
+        }
+    } else {
+        lines.push("STATUS:NEEDS-ACTION".to_string());
+    }
+    let categories: Vec<String> = todo.projects.iter().chain(todo.contexts.iter()).map(|c| escape(c)).collect();
+    if !categories.is_empty() {
+        lines.push(format!("CATEGORIES:{}", categories.join(",")));
+    }
+    if todo.is_priority {
+        lines.push("PRIORITY:1".to_string());
+    }
+    if !todo.recur.is_empty() {
+        lines.push(format!("RRULE:{}", rrule(todo)?));
+    }
+    if let Some(notes) = &todo.notes {
+        lines.push(format!("DESCRIPTION:{}", escape(&notes.join("\n"))));
+    }
+    lines.push("END:VTODO".to_string());
+    Ok(lines.iter().map(|l| fold(l)).join(""))
+}
+
+pub fn export(todos: &Vec<Todo>) -> Result<String, AppError> {
+    let stamp = Utc::now().format(DATE_TIME_FORMAT).to_string();
+    let mut out = fold("BEGIN:VCALENDAR") + &fold("VERSION:2.0") + &fold("PRODID:-//tlist//ul_todo_reader//EN");
+    for todo in todos {
+        out.push_str(&vtodo(todo, &stamp)?);
+    }
+    out.push_str(&fold("END:VCALENDAR"));
+    Ok(out)
+}
+
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    lines
+}
+
+/// Splits a comma separated value, ignoring commas escaped with a backslash.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = vec![String::new()];
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            ',' if !escaped => items.push(String::new()),
+            _ => items.last_mut().unwrap().push(c),
+        }
+        escaped = c == '\\' && !escaped;
+    }
+    items.iter().map(|i| unescape(i)).filter(|i| !i.is_empty()).collect()
+}
+
+fn parse_vtodo(lines: &[(String, String)]) -> Result<Todo, AppError> {
+    let mut todo = Todo::builder("").uuid("").build();
+    let mut summary = String::new();
+    let mut categories: Vec<String> = Vec::new();
+    let mut status_completed = false;
+    for (name, value) in lines {
+        match name.as_str() {
+            "UID" => todo.uuid = value.clone(),
+            "SUMMARY" => summary = unescape(value),
+            "DUE" => todo.due = parse_due(value)
+                                 .ok_or(AppError::InvalidICalendarError(format!("bad DUE: {}", value)))?,
+            "STATUS" => status_completed = value == "COMPLETED",
+            "COMPLETED" => todo.completed_date = parse_date_time(value).map(SerdeDateTime::from)
+                                 .ok_or(AppError::InvalidICalendarError(format!("bad COMPLETED: {}", value)))?,
+            "CREATED" => todo.created = parse_date_time(value).map(SerdeDateTime::from)
+                                 .ok_or(AppError::InvalidICalendarError(format!("bad CREATED: {}", value)))?,
+            "CATEGORIES" => categories.extend(split_list(value)),
+            "PRIORITY" => todo.is_priority = matches!(value.parse::<u8>(), Ok(1..=4)),
+            "RRULE" => (todo.recur, todo.recur_until) = parse_rrule(value)?,
+            "DESCRIPTION" => todo.notes = Some(unescape(value).lines().map(|l| l.to_string()).collect()),
+            _ => (),
+        }
+    }
+    if todo.uuid.is_empty() {
+        return Err(AppError::InvalidICalendarError("VTODO without a UID".to_string()));
+    }
+
+    let (contexts, projects) = get_contexts_and_projects(&summary);
+    for category in categories {
+        if !projects.contains(&category) && !contexts.contains(&category) {
+            summary.push_str(&format!(" +{}", category.replace(char::is_whitespace, "_")));
+        }
+    }
+    (todo.contexts, todo.projects) = get_contexts_and_projects(&summary);
+    todo.subject = summary;
+    todo.completed = status_completed || todo.completed_date.date().is_some();
+    if todo.completed {
+        todo.status = config::current().completed_status.clone();
+    }
+    Ok(todo)
+}
+
+pub fn parse(text: &str) -> Result<Vec<Todo>, AppError> {
+    let mut todos = Vec::new();
+    let mut current: Option<Vec<(String, String)>> = None;
+    for line in unfold(text) {
+        let Some((name_and_params, value)) = line.split_once(':') else { continue };
+        let name = name_and_params.split(';').next().unwrap_or_default().to_uppercase();
+        match (name.as_str(), value, current.as_mut()) {
+            ("BEGIN", "VTODO", None) => current = Some(Vec::new()),
+            ("END", "VTODO", Some(lines)) => {
+                todos.push(parse_vtodo(lines)?);
+                current = None;
+            },
+            (_, _, Some(lines)) => lines.push((name, value.to_string())),
+            _ => (),
+        }
+    }
+    if current.is_some() {
+        return Err(AppError::InvalidICalendarError("VTODO without END:VTODO".to_string()));
+    }
+    Ok(todos)
+}
+
+/// Merges the VTODOs of an .ics file into the list by UID, returning how many
+/// todos were added and how many were updated.
+pub fn import(todos: &mut Vec<Todo>, text: &str) -> Result<(usize, usize), AppError> {
+    let (mut added, mut updated) = (0, 0);
+    for mut incoming in parse(text)? {
+        match todos.iter_mut().find(|t| t.uuid == incoming.uuid) {
+            Some(existing) => {
+                incoming.id = existing.id;
+                incoming.archived = existing.archived;
+                if !incoming.completed && !existing.completed {
+                    incoming.status = existing.status.clone();
+                }
+                // properties missing from the VTODO leave the todo's own alone
+                if incoming.notes.is_none() {
+                    incoming.notes = existing.notes.clone();
+                }
+                if incoming.recur.is_empty() {
+                    incoming.recur = existing.recur.clone();
+                    incoming.recur_until = existing.recur_until.clone();
+                }
+                incoming.prev_recur_todo_uuid = existing.prev_recur_todo_uuid.clone();
+                incoming.parent_uuid = existing.parent_uuid.clone();
+                incoming.blocked_by = existing.blocked_by.clone();
+                incoming.time_entries = existing.time_entries.clone();
+                incoming.estimate = existing.estimate;
+                incoming.remind = existing.remind;
+                if existing.created.date().is_some() {
+                    incoming.created = existing.created.clone();
+                }
+                *existing = incoming;
+                updated += 1;
+            },
+            None => {
+                incoming.id = find_new_id(todos);
+                todos.push(incoming);
+                added += 1;
+            },
+        }
+    }
+    Ok((added, updated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveTime;
+
+    const GOLDEN_JSON: &str = include_str!("../tests/golden/todos.json");
+
+    fn golden() -> Vec<Todo> {
+        serde_json::from_str(GOLDEN_JSON).unwrap()
+    }
+
+    #[test]
+    fn test_export_vtodo() {
+        let todos = golden();
+
+        let out = vtodo(&todos[0], "20250101T000000Z").unwrap();
+
+        assert_eq!(out, [
+            "BEGIN:VTODO",
+            "UID:uuid-0",
+            "DTSTAMP:20250101T000000Z",
+            "SUMMARY:Review pull requests +work @computer",
+            "DUE;VALUE=DATE:20250106",
+            "STATUS:NEEDS-ACTION",
+            "CATEGORIES:work,computer",
+            "PRIORITY:1",
+            "RRULE:FREQ=WEEKLY",
+            "END:VTODO",
+            "",
+        ].join("\r\n"));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let todos = golden();
+
+        let parsed = parse(&export(&todos).unwrap()).unwrap();
+
+        assert_eq!(parsed.len(), todos.len());
+        for (todo, expected) in parsed.iter().zip(todos.iter()) {
+            assert_eq!(todo.uuid, expected.uuid);
+            assert_eq!(todo.subject, expected.subject);
+            assert_eq!(todo.projects, expected.projects);
+            assert_eq!(todo.contexts, expected.contexts);
+            assert_eq!(todo.due, expected.due);
+            assert_eq!(todo.completed, expected.completed);
+            assert_eq!(todo.completed_date, expected.completed_date);
+            assert_eq!(todo.is_priority, expected.is_priority);
+            assert_eq!(todo.recur, expected.recur);
+        }
+    }
+
+    #[test]
+    fn test_due_time() {
+        let mut todos = golden();
+        todos[0].due = todos[0].due.clone().with_time(NaiveTime::from_hms_opt(15, 30, 0));
+
+        let out = vtodo(&todos[0], "20250101T000000Z").unwrap();
+        let parsed = parse(&export(&todos).unwrap()).unwrap();
+
+        assert!(out.contains("DUE:20250106T153000\r\n"));
+        assert_eq!(parsed[0].due, todos[0].due);
+    }
+
+    #[test]
+    fn test_created() {
+        let mut todos = golden();
+        todos[0].created = parse_date_time("20250102T090000Z").map(SerdeDateTime::from).unwrap();
+
+        let out = vtodo(&todos[0], "20250101T000000Z").unwrap();
+        let parsed = parse(&export(&todos).unwrap()).unwrap();
+
+        assert!(out.contains("DTSTAMP:20250101T000000Z\r\nSUMMARY:Review pull requests +work @computer\r\nCREATED:20250102T090000Z\r\n"));
+        assert_eq!(parsed[0].created, todos[0].created);
+        assert_eq!(parsed[1].created.date(), None);
+    }
+
+    #[test]
+    fn test_fold_and_escape() {
+        let mut todos = golden();
+        todos[1].subject = format!("{}; with, punctuation", "a long subject ".repeat(8));
+        todos[1].projects = vec![];
+        todos[1].contexts = vec![];
+        todos[1].notes = Some(vec!["first note".to_string(), "second note".to_string()]);
+
+        let text = export(&todos).unwrap();
+        assert!(text.split("\r\n").all(|l| l.len() <= FOLD_WIDTH));
+
+        let parsed = parse(&text).unwrap();
+        assert_eq!(parsed[1].subject, todos[1].subject);
+        assert_eq!(parsed[1].notes, todos[1].notes);
+    }
+
+    #[test]
+    fn test_rrule() {
+        assert_eq!(parse_rrule("FREQ=DAILY;INTERVAL=3"), Ok(("every 3 days".to_string(), SerdeDate::new_empty())));
+        assert_eq!(parse_rrule("FREQ=WEEKLY;BYDAY=MO,TH"), Ok(("every monday,thursday".to_string(), SerdeDate::new_empty())));
+        assert_eq!(parse_rrule("FREQ=WEEKLY;INTERVAL=2"), Ok(("every 14 days".to_string(), SerdeDate::new_empty())));
+        assert_eq!(parse_rrule("FREQ=MONTHLY;UNTIL=20251231T000000Z").map(|(_, u)| u.date()), Ok(NaiveDate::from_ymd_opt(2025, 12, 31)));
+        assert!(parse_rrule("FREQ=MONTHLY;BYMONTHDAY=-1").is_err());
+    }
+
+    #[test]
+    fn test_rrule_bad_interval() {
+        assert!(parse_rrule("FREQ=WEEKLY;INTERVAL=0").is_err());
+        assert!(parse_rrule("FREQ=DAILY;INTERVAL=0").is_err());
+        assert!(parse_rrule("FREQ=WEEKLY;INTERVAL=18446744073709551615").is_err());
+    }
+
+    #[test]
+    fn test_import_merges_by_uid() {
+        let mut todos = golden();
+        let text = [
+            "BEGIN:VCALENDAR",
+            "BEGIN:VTODO",
+            "UID:uuid-1",
+            "SUMMARY:Buy oat milk +groceries @town",
+            "STATUS:COMPLETED",
+            "COMPLETED:20250104T100000Z",
+            "END:VTODO",
+            "BEGIN:VTODO",
+            "UID:from-calendar",
+            "SUMMARY:Dentist",
+            "DUE;VALUE=DATE:20250210",
+            "CATEGORIES:health,Family Stuff",
+            "PRIORITY:2",
+            "END:VTODO",
+            "END:VCALENDAR",
+        ].join("\r\n");
+
+        assert_eq!(import(&mut todos, &text), Ok((1, 1)));
+
+        let updated = todos.iter().find(|t| t.uuid == "uuid-1").unwrap();
+        assert_eq!(updated.id, 1);
+        assert_eq!(updated.subject, "Buy oat milk +groceries @town");
+        assert!(updated.completed);
+        assert_eq!(updated.status, "completed");
+
+        let added = todos.iter().find(|t| t.uuid == "from-calendar").unwrap();
+        assert_eq!(added.id, 7);
+        assert_eq!(added.subject, "Dentist +health +Family_Stuff");
+        assert_eq!(added.projects, vec!["health", "Family_Stuff"]);
+        assert!(added.is_priority);
+    }
+
+    #[test]
+    fn test_import_keeps_missing_properties() {
+        let mut todos = golden();
+        todos[0].notes = Some(vec!["bring laptop".to_string()]);
+        let vtodo = |extra: &str| format!("BEGIN:VTODO\r\nUID:uuid-0\r\nSUMMARY:Review pull requests +work\r\n{}END:VTODO\r\n", extra);
+
+        assert_eq!(import(&mut todos, &vtodo("")), Ok((0, 1)));
+        assert_eq!(todos[0].notes, Some(vec!["bring laptop".to_string()]));
+        assert_eq!(todos[0].recur, "weekly");
+
+        assert_eq!(import(&mut todos, &vtodo("DESCRIPTION:bring charger\r\nRRULE:FREQ=DAILY\r\n")), Ok((0, 1)));
+        assert_eq!(todos[0].notes, Some(vec!["bring charger".to_string()]));
+        assert_eq!(todos[0].recur, "daily");
+    }
+
+    #[test]
+    fn test_import_invalid() {
+        let mut todos = Vec::new();
+
+        assert!(import(&mut todos, "BEGIN:VTODO\r\nSUMMARY:x\r\nEND:VTODO\r\n").is_err());
+        assert!(import(&mut todos, "BEGIN:VTODO\r\nUID:a\r\n").is_err());
+        assert!(import(&mut todos, "BEGIN:VTODO\r\nUID:a\r\nDUE;VALUE=DATE:2025010é\r\nEND:VTODO\r\n").is_err());
+        assert!(todos.is_empty());
+    }
+}
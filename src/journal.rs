@@ -0,0 +1,310 @@
+use std::error::Error;
+use std::fs::{read_to_string, create_dir_all};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use tabled::{builder::Builder, settings::style::Style};
+use crate::{Todo, AppError, SerdeDateTime};
+use crate::modify::find_new_id;
+use crate::sync::same;
+use crate::todo_files::write_atomic;
+
+const JOURNAL_LIMIT: usize = 100;
+
+/// One todo as it was before and after an operation. `None` means the todo
+/// did not exist on that side, i.e. it was added or deleted.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Change {
+    before: Option<Todo>,
+    after: Option<Todo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Entry {
+    operation: String,
+    time: SerdeDateTime,
+    changes: Vec<Change>,
+}
+
+/// The entries before `position` can be undone, the ones from `position`
+/// onwards have been undone and can be redone.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct Journal {
+    entries: Vec<Entry>,
+    position: usize,
+}
+
+pub fn diff(before: &[Todo], after: &[Todo]) -> Vec<Change> {
+    let mut changes: Vec<Change> = before.iter().filter_map(|old| {
+        let new = after.iter().find(|t| t.uuid == old.uuid);
+        if new == Some(old) {
+            None
+        } else {
+            Some(Change { before: Some(old.clone()), after: new.cloned() })
+        }
+    }).collect();
+    after.iter()
+         .filter(|new| !before.iter().any(|t| t.uuid == new.uuid))
+         .for_each(|new| changes.push(Change { before: None, after: Some(new.clone()) }));
+    changes
+}
+
+fn apply(todos: &mut Vec<Todo>, changes: &[Change], from: fn(&Change) -> &Option<Todo>, to: fn(&Change) -> &Option<Todo>) -> Result<(), AppError> {
+    for change in changes {
+        let expected = from(change);
+        let uuid = expected.as_ref().or(to(change).as_ref()).map(|t| t.uuid.as_str()).unwrap_or_default();
+        let current = todos.iter().find(|t| t.uuid == uuid);
+        // an undone delete may have come back under a new id
+        let unchanged = match (current, expected) {
+            (Some(current), Some(expected)) => same(current, expected),
+            (current, expected) => current.is_none() && expected.is_none(),
+        };
+        if !unchanged {
+            return Err(AppError::JournalConflictError(uuid.to_string()));
+        }
+    }
+    for change in changes {
+        let mut current_id = None;
+        if let Some(old) = from(change) {
+            current_id = todos.iter().find(|t| t.uuid == old.uuid).map(|t| t.id);
+            todos.retain(|t| t.uuid != old.uuid);
+        }
+        if let Some(new) = to(change) {
+            let mut restored = new.clone();
+            restored.id = current_id.unwrap_or(restored.id);
+            if todos.iter().any(|t| t.id == restored.id) {
+                restored.id = find_new_id(todos);
+            }
+            todos.push(restored);
+        }
+    }
+    Ok(())
+}
+
+impl Journal {
+    pub fn load(path: &Path) -> Result<Journal, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Journal::default());
+        }
+        Ok(serde_json::from_str(&read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        write_atomic(path, &serde_json::to_string(self)?)
+    }
+
+    /// Records the difference between two versions of the list, dropping
+    /// anything that could still have been redone.
+    pub fn record(&mut self, operation: String, before: &[Todo], after: &[Todo]) {
+        let changes = diff(before, after);
+        if changes.is_empty() {
+            return;
+        }
+        self.entries.truncate(self.position);
+        self.entries.push(Entry { operation, time: SerdeDateTime::now(), changes });
+        if self.entries.len() > JOURNAL_LIMIT {
+            self.entries.drain(..self.entries.len() - JOURNAL_LIMIT);
+        }
+        self.position = self.entries.len();
+    }
+
+    /// Undoes the last `n` operations, returning their descriptions.
+    pub fn undo(&mut self, todos: &mut Vec<Todo>, n: usize) -> Result<Vec<String>, AppError> {
+        let mut undone = Vec::new();
+        while undone.len() < n && self.position > 0 {
+            let entry = &self.entries[self.position - 1];
+            apply(todos, &entry.changes, |c| &c.after, |c| &c.before)?;
+            undone.push(entry.operation.clone());
+            self.position -= 1;
+        }
+        Ok(undone)
+    }
+
+    /// Redoes the last `n` undone operations, returning their descriptions.
+    pub fn redo(&mut self, todos: &mut Vec<Todo>, n: usize) -> Result<Vec<String>, AppError> {
+        let mut redone = Vec::new();
+        while redone.len() < n && self.position < self.entries.len() {
+            let entry = &self.entries[self.position];
+            apply(todos, &entry.changes, |c| &c.before, |c| &c.after)?;
+            redone.push(entry.operation.clone());
+            self.position += 1;
+        }
+        Ok(redone)
+    }
+}
+
+pub fn history(journal: &Journal) {
+    if journal.entries.is_empty() {
+        println!("no history yet!");
+        return;
+    }
+    let mut builder = Builder::default();
+    for (i, entry) in journal.entries.iter().enumerate().rev() {
+        let state = if i < journal.position { "" } else { "(undone)" };
+        builder.push_record([
+            (i + 1).to_string(),
+            entry.time.to_string(),
+            entry.operation.clone(),
+            state.to_string(),
+        ]);
+    }
+    let mut table = builder.build();
+    table.with(Style::blank());
+    println!("{}", table);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{read_dir, remove_dir_all};
+    use uuid::Uuid;
+    use crate::modify::{add, delete, edit, delete_archived};
+    use crate::SerdeDate;
+
+    fn gen_todo() -> Vec<Todo> {
+        let mut todos = Vec::new();
+        assert!(add(&mut todos, "first +proj".to_string(), SerdeDate::new_empty(), None, SerdeDate::new_empty()).is_ok());
+        assert!(add(&mut todos, "second @ctx".to_string(), SerdeDate::new_empty(), None, SerdeDate::new_empty()).is_ok());
+        todos
+    }
+
+    fn record(journal: &mut Journal, todos: &mut Vec<Todo>, operation: &str, f: impl FnOnce(&mut Vec<Todo>)) {
+        let before = todos.clone();
+        f(todos);
+        journal.record(operation.to_string(), &before, todos);
+    }
+
+    #[test]
+    fn test_undo_redo_delete() {
+        let mut todos = gen_todo();
+        let original = todos.clone();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "delete #0", |t| assert!(delete(t, 0).is_ok()));
+
+        let r = journal.undo(&mut todos, 1);
+        assert_eq!(r, Ok(vec!["delete #0".to_string()]));
+        todos.sort_by_key(|t| t.id);
+        assert_eq!(todos, original);
+
+        let r = journal.redo(&mut todos, 1);
+        assert_eq!(r, Ok(vec!["delete #0".to_string()]));
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].id, 1);
+    }
+
+    #[test]
+    fn test_undo_edit_and_add() {
+        let mut todos = gen_todo();
+        let original = todos.clone();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "edit #1", |t| assert!(edit(t, 1, "changed".to_string(), None, None, None).is_ok()));
+        record(&mut journal, &mut todos, "add", |t| assert!(add(t, "third".to_string(), SerdeDate::new_empty(), None, SerdeDate::new_empty()).is_ok()));
+
+        let r = journal.undo(&mut todos, 5);
+
+        assert_eq!(r, Ok(vec!["add".to_string(), "edit #1".to_string()]));
+        todos.sort_by_key(|t| t.id);
+        assert_eq!(todos, original);
+    }
+
+    #[test]
+    fn test_undo_gc() {
+        let mut todos = gen_todo();
+        todos[0].archived = true;
+        todos[1].archived = true;
+        let original = todos.clone();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "gc", delete_archived);
+        assert!(todos.is_empty());
+
+        assert!(journal.undo(&mut todos, 1).is_ok());
+
+        todos.sort_by_key(|t| t.id);
+        assert_eq!(todos, original);
+    }
+
+    #[test]
+    fn test_record_drops_redo() {
+        let mut todos = gen_todo();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "delete #0", |t| assert!(delete(t, 0).is_ok()));
+        assert!(journal.undo(&mut todos, 1).is_ok());
+        record(&mut journal, &mut todos, "delete #1", |t| assert!(delete(t, 1).is_ok()));
+
+        assert_eq!(journal.redo(&mut todos, 1), Ok(vec![]));
+        assert_eq!(journal.entries.len(), 1);
+    }
+
+    #[test]
+    fn test_record_without_changes() {
+        let todos = gen_todo();
+        let mut journal = Journal::default();
+
+        journal.record("list".to_string(), &todos, &todos);
+
+        assert!(journal.entries.is_empty());
+    }
+
+    #[test]
+    fn test_undo_conflict() {
+        let mut todos = gen_todo();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "edit #0", |t| assert!(edit(t, 0, "changed".to_string(), None, None, None).is_ok()));
+        todos[0].subject = "changed elsewhere".to_string();
+
+        let r = journal.undo(&mut todos, 1);
+
+        assert_eq!(r, Err(AppError::JournalConflictError(todos[0].uuid.clone())));
+        assert_eq!(todos[0].subject, "changed elsewhere");
+    }
+
+    #[test]
+    fn test_undo_restores_reused_id() {
+        let mut todos = gen_todo();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "delete #0", |t| assert!(delete(t, 0).is_ok()));
+        assert!(add(&mut todos, "reuses id 0".to_string(), SerdeDate::new_empty(), None, SerdeDate::new_empty()).is_ok());
+
+        assert!(journal.undo(&mut todos, 1).is_ok());
+
+        let mut ids: Vec<u64> = todos.iter().map(|t| t.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_redo_after_restored_under_new_id() {
+        let mut todos = gen_todo();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "edit #0", |t| assert!(edit(t, 0, "changed".to_string(), None, None, None).is_ok()));
+        record(&mut journal, &mut todos, "delete #0", |t| assert!(delete(t, 0).is_ok()));
+        assert!(journal.undo(&mut todos, 1).is_ok());
+        todos.iter_mut().find(|t| t.subject == "changed").unwrap().id = 7;
+
+        assert!(journal.undo(&mut todos, 1).is_ok());
+        assert_eq!(todos.iter().find(|t| t.subject == "first +proj").map(|t| t.id), Some(7));
+        assert_eq!(journal.redo(&mut todos, 2), Ok(vec!["edit #0".to_string(), "delete #0".to_string()]));
+
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].subject, "second @ctx");
+    }
+
+    #[test]
+    fn test_save() {
+        let dir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4()));
+        let path = dir.join("journals").join("list.json");
+        let mut todos = gen_todo();
+        let mut journal = Journal::default();
+        record(&mut journal, &mut todos, "delete #0", |t| assert!(delete(t, 0).is_ok()));
+
+        assert!(journal.save(&path).is_ok());
+
+        let loaded = Journal::load(&path).unwrap();
+        assert_eq!((loaded.entries[0].operation.as_str(), loaded.entries[0].changes.len(), loaded.position), ("delete #0", 1, 1));
+        assert_eq!(read_dir(path.parent().unwrap()).unwrap().count(), 1);
+        remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,518 @@
+#![allow(clippy::ptr_arg)]
+
+pub mod agenda;
+pub mod config;
+pub mod serde_date_time;
+pub mod serde_date;
+pub mod serde_duration;
+pub mod list;
+pub mod modify;
+pub mod notes;
+pub mod todo_files;
+pub mod recur;
+pub mod filter;
+pub mod journal;
+pub mod todotxt;
+pub mod ical;
+pub mod tui;
+pub mod selection;
+pub mod timer;
+pub mod remind;
+pub mod server;
+pub mod sync;
+pub mod theme;
+
+use clap::ValueEnum;
+use itertools::Itertools;
+use std::error::Error;
+use std::fmt;
+use std::cmp::Ordering;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+pub use crate::serde_date_time::SerdeDateTime;
+pub use crate::serde_date::SerdeDate;
+pub use crate::serde_duration::SerdeDuration;
+use crate::timer::TimeEntry;
+
+#[derive(Debug, PartialEq)]
+pub enum AppError {
+    IdNotFoundError(u64),
+    IdsNotFoundError(Vec<u64>),
+    InvalidSelectionError(String),
+    EmptySelectionError,
+    InvalidParentError(u64, u64),
+    DependencyCycleError(u64, u64),
+    NotBlockedError(u64, u64),
+    TimerRunningError(u64),
+    NoTimerRunningError,
+    InvalidDateError(String),
+    InvalidDaysError(u64),
+    InvalidDurationError(String),
+    NoteNotFoundError(u64, usize),
+    NoConfigurationDirectory,
+    TodolistNotFoundError(String),
+    NoSyncEndpointError(String),
+    RemoteChangedError(String),
+    InvalidConfigError(String),
+    InvalidSortError(String),
+    InvalidRecurrenceError(String),
+    UntilWithoutRecurrenceError,
+    InvalidFilterError(String, String),
+    TodolistChangedError(String),
+    JournalConflictError(String),
+    GitError(String),
+    InvalidICalendarError(String),
+}
+impl Error for AppError {}
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppError::IdNotFoundError(i) => write!(f, "ID not found: {}", i),
+            AppError::IdsNotFoundError(ids) if ids.len() == 1 => write!(f, "ID not found: {}", ids[0]),
+            AppError::IdsNotFoundError(ids) => write!(f, "IDs not found: {}; nothing was changed", ids.iter().join(", ")),
+            AppError::InvalidParentError(i, p) => write!(f, "Todo {} cannot be a subtask of todo {}, which is itself or one of its subtasks", i, p),
+            AppError::DependencyCycleError(i, on) => write!(f, "Todo {} cannot be blocked on todo {}, which already waits on it", i, on),
+            AppError::NotBlockedError(i, on) => write!(f, "Todo {} is not blocked on todo {}", i, on),
+            AppError::TimerRunningError(i) => write!(f, "A timer is already running on todo {}; stop it first", i),
+            AppError::NoTimerRunningError => write!(f, "No timer is running"),
+            AppError::InvalidDateError(d) => write!(f, "Invalid date \"{}\"", d),
+            AppError::InvalidDaysError(d) => write!(f, "Invalid number of days {}: expected 1 to {}", d, agenda::MAX_DAYS),
+            AppError::InvalidDurationError(d) => write!(f, "Invalid duration \"{}\": expected something like 2h, 30m or 1h30m", d),
+            AppError::InvalidSelectionError(s) => write!(f, "Invalid id list \"{}\": expected ids and ranges like 3,5,9-12", s),
+            AppError::EmptySelectionError => write!(f, "No todos selected: give ids like 3,5,9-12 or a filter"),
+            AppError::NoteNotFoundError(j, i) => write!(f, "Note number {} not found on todo number {}!", i, j),
+            AppError::NoConfigurationDirectory => write!(f, "The configuration directory has not been set up yet!"),
+            AppError::TodolistNotFoundError(n) => write!(f, "Todolist \"{}\" not found", n),
+            AppError::InvalidConfigError(e) => write!(f, "Invalid configuration: {}", e),
+            AppError::InvalidSortError(s) => write!(f, "Invalid sort key \"{}\": expected due, priority, subject, id, created or status, optionally with a leading -", s),
+            AppError::RemoteChangedError(e) => write!(f, "{} was changed by someone else during the sync; nothing was saved, sync again", e),
+            AppError::NoSyncEndpointError(n) => write!(f, "No sync endpoint is set for {}; pass one with --endpoint", n),
+            AppError::InvalidFilterError(t, reason) => write!(f, "Invalid filter term \"{}\": {}", t, reason),
+            AppError::TodolistChangedError(p) => write!(f, "{} was changed by another program while tlist was running; no changes were saved", p),
+            AppError::JournalConflictError(u) => write!(f, "Todo {} has changed since this operation was recorded; it cannot be undone or redone", u),
+            AppError::GitError(e) => write!(f, "git failed: {}", e),
+            AppError::InvalidICalendarError(e) => write!(f, "Invalid iCalendar file: {}", e),
+            AppError::InvalidRecurrenceError(r) => write!(f, "Invalid recurrence \"{}\": expected daily, weekdays, weekly, monthly, yearly, \"every N days\" with N up to {} or \"every monday,thursday\"", r, agenda::MAX_DAYS),
+            AppError::UntilWithoutRecurrenceError => write!(f, "An end date (--until) needs a recurrence; set one with --recur"),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum FileFormat {
+    Todotxt,
+    Ical
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum OutputFormat {
+    #[default]
+    Table,
+    Json,
+    Ndjson,
+    Csv
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum GroupOption {
+    Project,
+    Context,
+    Status
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    Id,
+    Done,
+    Due,
+    Status,
+    Subject,
+    Projects,
+    Contexts,
+    #[value(name = "completed_date")]
+    CompletedDate,
+    Uuid,
+    Age
+}
+
+pub const DEFAULT_COLUMNS: [Column; 5] = [Column::Id, Column::Done, Column::Due, Column::Status, Column::Subject];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortField {
+    Due,
+    Priority,
+    Subject,
+    Id,
+    Created,
+    Status
+}
+
+/// A field to sort by, descending when written with a leading '-'.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = AppError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, descending) = match s.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (s, false),
+        };
+        let field = SortField::from_str(name, true).map_err(|_| AppError::InvalidSortError(s.to_string()))?;
+        Ok(SortKey { field, descending })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Todo {
+    id: u64,
+    uuid: String,
+    subject: String,
+    projects: Vec<String>,
+    contexts: Vec<String>,
+    #[serde(flatten, with = "serde_date::due_fields")]
+    due: SerdeDate,
+    completed: bool, 
+    completed_date: SerdeDateTime,
+    status: String,
+    archived: bool, 
+    is_priority: bool,
+    notes: Option<Vec<String>>,
+    recur: String,
+    recur_until: SerdeDate,
+    prev_recur_todo_uuid: String,
+    #[serde(default)]
+    parent_uuid: String,
+    #[serde(default)]
+    blocked_by: Vec<String>,
+    #[serde(default)]
+    time_entries: Vec<TimeEntry>,
+    #[serde(default)]
+    estimate: SerdeDuration,
+    #[serde(default)]
+    remind: SerdeDuration,
+    #[serde(default)]
+    created: SerdeDateTime,
+}
+
+pub fn sort_todo_list(list: &mut Vec<Todo>) {
+    list.sort_by(|a, b| {
+        if (!a.is_priority).cmp(&(!b.is_priority)) != Ordering::Equal {
+            return (!a.is_priority).cmp(&(!b.is_priority)); //priority tasks at the top (lesser)
+        }
+        if !a.due.is_some() && !b.due.is_some() {
+            return a.subject.cmp(&b.subject);
+        }
+        a.due.cmp(&b.due)
+    });
+}
+
+/// Sorts by each key in turn, then by id. Todos without a due or creation
+/// date come last when sorting by it, whichever the direction.
+pub fn sort_by_keys(list: &mut Vec<Todo>, keys: &[SortKey]) {
+    list.sort_by(|a, b| {
+        keys.iter().map(|key| {
+            let missing = |t: &Todo| match key.field {
+                SortField::Due => !t.due.is_some(),
+                SortField::Created => t.created.date().is_none(),
+                _ => false,
+            };
+            let order = match key.field {
+                SortField::Due => a.due.cmp(&b.due),
+                SortField::Priority => a.is_priority.cmp(&b.is_priority),
+                SortField::Subject => a.subject.to_lowercase().cmp(&b.subject.to_lowercase()),
+                SortField::Id => a.id.cmp(&b.id),
+                SortField::Created => a.created.cmp(&b.created),
+                SortField::Status => a.status.cmp(&b.status),
+            };
+            match (missing(a), missing(b)) {
+                (false, false) if key.descending => order.reverse(),
+                (false, false) => order,
+                (x, y) => x.cmp(&y),
+            }
+        }).find(|o| o.is_ne()).unwrap_or(a.id.cmp(&b.id))
+    });
+}
+
+impl Todo {
+    pub fn builder(subject: &str) -> TodoBuilder {
+        TodoBuilder::new(subject)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    pub fn projects(&self) -> &[String] {
+        &self.projects
+    }
+
+    pub fn contexts(&self) -> &[String] {
+        &self.contexts
+    }
+
+    pub fn due(&self) -> &SerdeDate {
+        &self.due
+    }
+
+    pub fn completed(&self) -> bool {
+        self.completed
+    }
+
+    pub fn completed_date(&self) -> &SerdeDateTime {
+        &self.completed_date
+    }
+
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+
+    pub fn archived(&self) -> bool {
+        self.archived
+    }
+
+    pub fn is_priority(&self) -> bool {
+        self.is_priority
+    }
+
+    pub fn notes(&self) -> &[String] {
+        self.notes.as_deref().unwrap_or_default()
+    }
+
+    pub fn recur(&self) -> &str {
+        &self.recur
+    }
+
+    pub fn recur_until(&self) -> &SerdeDate {
+        &self.recur_until
+    }
+
+    pub fn prev_recur_todo_uuid(&self) -> &str {
+        &self.prev_recur_todo_uuid
+    }
+
+    pub fn parent_uuid(&self) -> &str {
+        &self.parent_uuid
+    }
+
+    /// The uuids of the todos that have to be completed before this one.
+    pub fn blocked_by(&self) -> &[String] {
+        &self.blocked_by
+    }
+
+    pub fn time_entries(&self) -> &[TimeEntry] {
+        &self.time_entries
+    }
+
+    pub fn estimate(&self) -> &SerdeDuration {
+        &self.estimate
+    }
+
+    pub fn remind(&self) -> &SerdeDuration {
+        &self.remind
+    }
+
+    pub fn created(&self) -> &SerdeDateTime {
+        &self.created
+    }
+}
+
+pub struct TodoBuilder {
+    todo: Todo,
+}
+
+impl TodoBuilder {
+    pub fn new(subject: &str) -> Self {
+        let (ctx, projs) = modify::get_contexts_and_projects(subject);
+        TodoBuilder {
+            todo: Todo {
+                id: 0,
+                uuid: Uuid::new_v4().to_string(),
+                subject: subject.to_string(),
+                projects: projs,
+                contexts: ctx,
+                due: SerdeDate::new_empty(),
+                completed: false,
+                completed_date: SerdeDateTime::new_empty(),
+                status: "".to_string(),
+                archived: false,
+                is_priority: false,
+                notes: None,
+                recur: "".to_string(),
+                recur_until: SerdeDate::new_empty(),
+                prev_recur_todo_uuid: "".to_string(),
+                parent_uuid: "".to_string(),
+                blocked_by: vec![],
+                time_entries: vec![],
+                estimate: SerdeDuration::new_empty(),
+                remind: SerdeDuration::new_empty(),
+                created: SerdeDateTime::new_empty(),
+            }
+        }
+    }
+
+    pub fn id(mut self, id: u64) -> Self {
+        self.todo.id = id;
+        self
+    }
+
+    pub fn uuid(mut self, uuid: &str) -> Self {
+        self.todo.uuid = uuid.to_string();
+        self
+    }
+
+    pub fn due(mut self, due: SerdeDate) -> Self {
+        self.todo.due = due;
+        self
+    }
+
+    pub fn status(mut self, status: &str) -> Self {
+        self.todo.status = status.to_string();
+        self
+    }
+
+    pub fn priority(mut self, set: bool) -> Self {
+        self.todo.is_priority = set;
+        self
+    }
+
+    pub fn notes(mut self, notes: Vec<String>) -> Self {
+        self.todo.notes = if notes.is_empty() { None } else { Some(notes) };
+        self
+    }
+
+    /// Fails with `InvalidRecurrenceError` if the rule cannot be parsed.
+    pub fn recur(mut self, recur: &str) -> Result<Self, AppError> {
+        self.todo.recur = recur::normalize_recur(recur)?;
+        Ok(self)
+    }
+
+    pub fn recur_until(mut self, until: SerdeDate) -> Self {
+        self.todo.recur_until = until;
+        self
+    }
+
+    pub fn parent_uuid(mut self, parent: &str) -> Self {
+        self.todo.parent_uuid = parent.to_string();
+        self
+    }
+
+    pub fn estimate(mut self, estimate: SerdeDuration) -> Self {
+        self.todo.estimate = estimate;
+        self
+    }
+
+    pub fn remind(mut self, remind: SerdeDuration) -> Self {
+        self.todo.remind = remind;
+        self
+    }
+
+    pub fn created(mut self, created: SerdeDateTime) -> Self {
+        self.todo.created = created;
+        self
+    }
+
+    pub fn build(self) -> Todo {
+        self.todo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let todo = Todo::builder("call +work @phone")
+            .id(3)
+            .priority(true)
+            .recur("every Mon")
+            .unwrap()
+            .build();
+
+        assert_eq!(todo.id(), 3);
+        assert!(!todo.uuid().is_empty());
+        assert_eq!(todo.projects(), ["work"]);
+        assert_eq!(todo.contexts(), ["phone"]);
+        assert!(todo.is_priority());
+        assert_eq!(todo.recur(), "every monday");
+        assert!(todo.notes().is_empty());
+    }
+
+    #[test]
+    fn test_sort_by_due_time() {
+        let due = |s: &str| serde_json::from_str::<SerdeDate>(&format!("\"{}\"", s)).unwrap();
+        let mut todos: Vec<Todo> = ["2025-01-15", "2025-01-15 16:00", "2025-01-15 09:00", "2025-01-14"].iter()
+            .map(|d| Todo { due: due(d), ..Todo::builder(d).build() })
+            .collect();
+
+        sort_todo_list(&mut todos);
+
+        let order: Vec<&str> = todos.iter().map(|t| t.subject()).collect();
+        assert_eq!(order, ["2025-01-14", "2025-01-15 09:00", "2025-01-15 16:00", "2025-01-15"]);
+    }
+
+    #[test]
+    fn test_due_time_field() {
+        let json = r#"{"id":0,"uuid":"u","subject":"call","projects":[],"contexts":[],"due":"2025-01-15","due_time":"15:00","completed":false,"completed_date":"","status":"","archived":false,"is_priority":false,"notes":null,"recur":"","recur_until":"","prev_recur_todo_uuid":""}"#;
+        let todo: Todo = serde_json::from_str(json).unwrap();
+
+        assert_eq!(todo.due().time(), chrono::NaiveTime::from_hms_opt(15, 0, 0));
+        let saved = serde_json::to_string(&todo).unwrap();
+        assert!(saved.contains(r#""due":"2025-01-15","due_time":"15:00","completed":false"#));
+
+        let untimed = Todo { due: SerdeDate::from(todo.due().date().unwrap()), ..todo.clone() };
+        assert!(!serde_json::to_string(&untimed).unwrap().contains("due_time"));
+
+        let legacy: Todo = serde_json::from_str(&json.replace(r#""2025-01-15","due_time":"15:00""#, r#""2025-01-15 15:00""#)).unwrap();
+        assert_eq!(legacy, todo);
+        assert!(serde_json::from_str::<Todo>(&json.replace("15:00", "3pm")).is_err());
+    }
+
+    #[test]
+    fn test_sort_by_keys() {
+        let due = |s: &str| serde_json::from_str::<SerdeDate>(&format!("\"{}\"", s)).unwrap();
+        let mut todos: Vec<Todo> = vec![
+            Todo::builder("b").id(0).build(),
+            Todo { due: due("2025-01-15"), ..Todo::builder("c").id(1).build() },
+            Todo::builder("a").id(2).priority(true).build(),
+            Todo { due: due("2025-01-14"), ..Todo::builder("a").id(3).build() },
+        ];
+        let keys = |s: &str| s.split(',').map(|k| k.parse::<SortKey>().unwrap()).collect::<Vec<SortKey>>();
+
+        sort_by_keys(&mut todos, &keys("-priority,due"));
+        assert_eq!(todos.iter().map(|t| t.id).collect::<Vec<u64>>(), [2, 3, 1, 0]);
+        sort_by_keys(&mut todos, &keys("-due"));
+        assert_eq!(todos.iter().map(|t| t.id).collect::<Vec<u64>>(), [1, 3, 0, 2]);
+        sort_by_keys(&mut todos, &keys("subject,-id"));
+        assert_eq!(todos.iter().map(|t| t.id).collect::<Vec<u64>>(), [3, 2, 0, 1]);
+        assert_eq!("-bogus".parse::<SortKey>(), Err(AppError::InvalidSortError("-bogus".to_string())));
+    }
+
+    #[test]
+    fn test_builder_invalid_recur() {
+        let r = Todo::builder("subject").recur("sometimes");
+
+        assert!(matches!(r, Err(AppError::InvalidRecurrenceError(_))));
+    }
+}
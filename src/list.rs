@@ -1,37 +1,77 @@
 use colored::Colorize;
-use tabled::{builder::Builder, settings::{Span, style::Style, Color, themes::Colorization}, Table};
+use tabled::{builder::Builder, settings::{Span, style::Style, themes::Colorization}, Table};
 use itertools::Itertools;
 use std::iter;
 use std::collections::HashMap;
-use std::cmp::Ordering;
-use crate::{Todo, GroupOption};
+use std::error::Error;
+use std::io::{self, Write};
+use serde_json::{Map, Value};
+use chrono::{Local, NaiveTime, TimeDelta};
+use crate::{Column, GroupOption, OutputFormat, SortKey, Todo, sort_by_keys};
+use crate::filter::Filter;
 use crate::serde_date::SerdeDate;
+use crate::serde_duration::SerdeDuration;
+use crate::modify::series_anchor;
+use crate::recur::Recurrence;
+use crate::theme;
 
-macro_rules! bold_if {
-    ($cond:expr, $val:expr) => {
-        if $cond {
-            $val.bold().to_string()
-        } else {
-            $val
-        }
+fn colorize_subject(k: &str) -> String {
+    k.split_whitespace().map(|word| theme::current().word(word)).join(" ")
+}
+
+fn subject_cell(todos: &Vec<Todo>, item: &Todo) -> String {
+    let subject = colorize_subject(&item.subject);
+    if item.recur.is_empty() || !item.recur_until.is_some() {
+        return subject;
+    }
+    let Ok(rule) = Recurrence::try_from(item.recur.as_str()) else { return subject };
+    let next_due = SerdeDate::from(rule.next_due(item.due.date(), series_anchor(todos, item), Local::now().date_naive()));
+    if next_due > item.recur_until {
+        format!("{} {}", subject, format!("(last, series ends {})", item.recur_until).dimmed())
+    } else {
+        subject
     }
 }
 
-fn colorize_subject(k: &str) -> String {
-    k.split_whitespace().map(|word: &str| -> String {
-        match word.chars().nth(0) {
-            Some('+') => word.purple().to_string(),
-            Some('@') => word.green().to_string(),
-            _ => word.to_string()
+/// Orders a group so that subtasks follow their parent, pairing each todo
+/// with its depth. A subtask whose parent is not in the group is shown at
+/// the top level.
+fn nest<'a>(group: &[&'a Todo]) -> Vec<(&'a Todo, usize)> {
+    fn visit<'a>(group: &[&'a Todo], todo: &'a Todo, depth: usize, out: &mut Vec<(&'a Todo, usize)>) {
+        if out.iter().any(|(t, _)| std::ptr::eq(*t, todo)) {
+            return;
+        }
+        out.push((todo, depth));
+        for child in group.iter().filter(|t| !todo.uuid.is_empty() && t.parent_uuid == todo.uuid) {
+            visit(group, child, depth + 1, out);
         }
-    }).join(" ")
+    }
+    let mut out = Vec::with_capacity(group.len());
+    for root in group.iter().filter(|t| t.parent_uuid.is_empty() || !group.iter().any(|p| p.uuid == t.parent_uuid)) {
+        visit(group, root, 0, &mut out);
+    }
+    // whatever is left is part of a cycle in a hand-edited file
+    for todo in group {
+        visit(group, todo, 0, &mut out);
+    }
+    out
+}
+
+/// " 2/5" for a todo with subtasks, counting its direct children.
+fn progress(todos: &Vec<Todo>, item: &Todo) -> String {
+    let children: Vec<&Todo> = todos.iter().filter(|t| t.parent_uuid == item.uuid).collect();
+    if children.is_empty() {
+        return String::new();
+    }
+    let done = children.iter().filter(|t| t.completed).count();
+    format!(" {}", format!("{}/{}", done, children.len()).dimmed())
 }
 
-fn red_if_overdue(due: &SerdeDate) -> String {
-    if due.cmp(&SerdeDate::today()) == Ordering::Less {
-        due.to_string()
+fn estimate(item: &Todo) -> String {
+    if item.estimate.is_some() {
+        format!(" {}", format!("~{}", item.estimate).dimmed())
     } else {
-        due.to_string().red().to_string()
+        String::new()
     }
 }
 
@@ -39,7 +79,7 @@ const FULL_GROUP_LABEL: &str = "All";
 const NO_PROJECT_LABEL: &str = "No projects";
 const NO_CONTEXT_LABEL: &str = "No contexts";
 const NO_STATUS_LABEL: &str = "No status";
-fn todo_grouping(todos: &Vec<Todo>, grouping: Option<GroupOption>) -> HashMap<&str, Vec<&Todo>> {
+pub fn todo_grouping(todos: &Vec<Todo>, grouping: Option<GroupOption>) -> HashMap<&str, Vec<&Todo>> {
     match grouping {
         None => {
             let mut todo_pointer_vec = Vec::with_capacity(todos.len());
@@ -91,67 +131,174 @@ fn todo_grouping(todos: &Vec<Todo>, grouping: Option<GroupOption>) -> HashMap<&s
     }
 }
 
-pub fn disp_list(todos: &Vec<Todo>, grouping: Option<GroupOption>, show_notes: bool) -> Vec<(&str, Table)> {
-    let mut lists: Vec<(&str, Table)> = Vec::new();
-    let grouped_todo: HashMap<&str, Vec<&Todo>> = todo_grouping(todos, grouping);
-
-    for (title, todo_group) in grouped_todo.iter() {
-        let mut builder = Builder::default();
-        let mut note_rows = Vec::new();
-        let mut contains_unarchived_item = false;
-        for item in todo_group.iter() {
-            if !item.archived {
-                contains_unarchived_item = true;
-                let record = [
-                    bold_if!(item.is_priority, item.id.to_string()), 
-                    bold_if!(item.is_priority, if item.completed { "[x]" } else { "[ ]" }.to_string()),
-                    bold_if!(item.is_priority, red_if_overdue(&item.due)),
-                    bold_if!(item.is_priority, item.status.to_string()),
-                    bold_if!(item.is_priority, colorize_subject(&item.subject)),
-                ];
-                builder.push_record(record);
-                if show_notes {
-                    if let Some(notes) = &item.notes {
-                        notes.iter().enumerate().for_each(|(i, note)| {
-                            note_rows.push((builder.count_records(), 2));
-                            builder.push_record(["".to_string(), i.to_string(), note.to_string()]);
-                        });
-                    }
+/// Days since the todo was created, like "12d".
+fn age(item: &Todo) -> String {
+    item.created.date()
+        .map(|created| format!("{}d", (Local::now() - created).max(TimeDelta::zero()).num_days()))
+        .unwrap_or_default()
+}
+
+fn cell(todos: &Vec<Todo>, item: &Todo, depth: usize, column: Column) -> String {
+    let theme = theme::current();
+    match column {
+        Column::Id => item.id.to_string(),
+        Column::Done => if item.completed { "[x]" } else { "[ ]" }.to_string(),
+        Column::Due => theme.due(&item.due.to_string(), item.due.is_overdue()),
+        Column::Status => item.status.to_string(),
+        Column::Subject => format!("{}{}{}{}", "  ".repeat(depth), subject_cell(todos, item), progress(todos, item), estimate(item)),
+        Column::Projects => item.projects.iter().map(|p| theme.word(&format!("+{}", p))).join(" "),
+        Column::Contexts => item.contexts.iter().map(|c| theme.word(&format!("@{}", c))).join(" "),
+        Column::CompletedDate => item.completed_date.to_string(),
+        Column::Uuid => item.uuid.clone(),
+        Column::Age => age(item),
+    }
+}
+
+/// A row with `cells` in its last columns, for notes and the estimate total.
+fn trailing_row(columns: &[Column], cells: &[String]) -> Vec<String> {
+    let cells = &cells[cells.len().saturating_sub(columns.len())..];
+    iter::repeat_n(String::new(), columns.len() - cells.len()).chain(cells.iter().cloned()).collect()
+}
+
+/// The table for one group of `todos`, styled like `tlist list`.
+pub fn group_table(todos: &Vec<Todo>, todo_group: &[&Todo], show_notes: bool, columns: &[Column]) -> Table {
+    let mut builder = Builder::default();
+    let mut note_rows = Vec::new();
+    for (item, depth) in nest(todo_group) {
+        let theme = theme::current();
+        builder.push_record(columns.iter().map(|c| theme.cell(cell(todos, item, depth, *c), item.is_priority)));
+        if show_notes && let Some(notes) = &item.notes {
+            notes.iter().enumerate().for_each(|(i, note)| {
+                if columns.len() >= 3 {
+                    note_rows.push((builder.count_records(), 2));
+                    builder.push_record(["".to_string(), i.to_string(), note.to_string()]);
+                } else {
+                    builder.push_record(trailing_row(columns, &[i.to_string(), note.to_string()]));
                 }
-            }
+            });
         }
+    }
 
-        if contains_unarchived_item {
-            let idcol = Color::FG_YELLOW;
-            let complcol = Color::FG_BLUE;
-            let duecol = Color::FG_YELLOW;
-            let statuscol = Color::FG_RED;
-            let subjectcol = Color::FG_BRIGHT_WHITE;
-
-            let mut table = builder.build();
-            table.with(Style::blank())
-                .with(Colorization::columns([idcol, complcol, duecol, statuscol, subjectcol]));
-            for row in note_rows {
-                table.modify(row, Span::column(3));
-            }
+    let total: SerdeDuration = todo_group.iter().map(|t| t.estimate).sum();
+    if total.is_some() {
+        builder.push_record(trailing_row(columns, &["estimate".to_string(), total.to_string()]));
+    }
+
+    let mut table = builder.build();
+    table.with(Style::blank());
+    if colored::control::SHOULD_COLORIZE.should_colorize() {
+        table.with(Colorization::columns(columns.iter().map(|c| theme::current().column(*c))));
+    }
+    for row in note_rows {
+        table.modify(row, Span::column(columns.len() as isize - 2));
+    }
+    table
+}
+
+pub fn disp_list<'a>(todos: &'a Vec<Todo>, grouping: Option<GroupOption>, show_notes: bool, columns: &[Column]) -> Vec<(&'a str, Table)> {
+    sorted_groups(todos, grouping).into_iter()
+        .map(|(title, todo_group)| (title, group_table(todos, &todo_group, show_notes, columns)))
+        .collect()
+}
+
+fn sorted_groups(todos: &Vec<Todo>, grouping: Option<GroupOption>) -> Vec<(&str, Vec<&Todo>)> {
+    todo_grouping(todos, grouping).into_iter().sorted_by_key(|(title, _)| *title).collect()
+}
+
+/// The groups as a JSON object of group title to todos, each todo in the
+/// same shape as the todolist file. Without grouping there is one "All" group.
+pub fn to_json(todos: &Vec<Todo>, grouping: Option<GroupOption>) -> Result<String, serde_json::Error> {
+    let mut groups = Map::new();
+    for (title, todo_group) in sorted_groups(todos, grouping) {
+        groups.insert(title.to_string(), serde_json::to_value(todo_group)?);
+    }
+    serde_json::to_string_pretty(&Value::Object(groups))
+}
+
+/// The serialized todo, with a leading "group" field when grouping. A todo
+/// in several groups is emitted once per group.
+fn grouped_todo(title: &str, todo: &Todo, grouping: Option<GroupOption>) -> Result<Map<String, Value>, serde_json::Error> {
+    let mut object = Map::new();
+    if grouping.is_some() {
+        object.insert("group".to_string(), Value::from(title));
+    }
+    if let Value::Object(fields) = serde_json::to_value(todo)? {
+        object.extend(fields);
+    }
+    Ok(object)
+}
+
+pub fn to_ndjson(todos: &Vec<Todo>, grouping: Option<GroupOption>) -> Result<String, serde_json::Error> {
+    let mut lines = String::new();
+    for (title, todo_group) in sorted_groups(todos, grouping) {
+        for todo in todo_group {
+            lines.push_str(&serde_json::to_string(&grouped_todo(title, todo, grouping)?)?);
+            lines.push('\n');
+        }
+    }
+    Ok(lines)
+}
+
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => "".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(csv_field).join(";"),
+        other => other.to_string(),
+    }
+}
 
-            lists.push((*title, table));
+/// One row per todo with the todolist file's field names as the header.
+/// Lists such as projects and notes are joined with ';'.
+pub fn to_csv(todos: &Vec<Todo>, grouping: Option<GroupOption>) -> Result<String, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // a due time, so that the header has every field a todo can have
+    let full = Todo::builder("").due(SerdeDate::today().with_time(Some(NaiveTime::MIN))).build();
+    let header = grouped_todo("", &full, grouping)?;
+    writer.write_record(header.keys())?;
+    for (title, todo_group) in sorted_groups(todos, grouping) {
+        for todo in todo_group {
+            let row = grouped_todo(title, todo, grouping)?;
+            writer.write_record(header.keys().map(|k| row.get(k).map(csv_field).unwrap_or_default()))?;
         }
     }
-    lists
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+/// Writes machine-readable output, treating a closed pipe (`tlist list -o
+/// ndjson | head`) as the reader being done rather than as an error.
+fn write_stdout(output: &str) -> Result<(), Box<dyn Error>> {
+    match io::stdout().lock().write_all(output.as_bytes()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        r => Ok(r?),
+    }
 }
 
-pub fn list(todos: &Vec<Todo>, grouping: Option<GroupOption>, show_notes: bool) -> () {
-    let lists = disp_list(todos, grouping, show_notes);
-    for (title, table) in lists {
-        println!("{}:\n{}", title, table);
+/// Lists the todos matching `filter`, in the order of `sort` if given and
+/// with `columns` in table output.
+pub fn list(todos: &Vec<Todo>, grouping: Option<GroupOption>, show_notes: bool, filter: &Filter, output: OutputFormat, columns: &[Column], sort: &[SortKey]) -> Result<(), Box<dyn Error>> {
+    let mut filtered: Vec<Todo> = todos.iter().filter(|t| filter.matches(t)).cloned().collect();
+    if !sort.is_empty() {
+        sort_by_keys(&mut filtered, sort);
+    }
+    match output {
+        OutputFormat::Table => {
+            let lists = disp_list(&filtered, grouping, show_notes, columns);
+            for (title, table) in lists {
+                println!("{}:\n{}", title, table);
+            }
+        },
+        OutputFormat::Json => write_stdout(&(to_json(&filtered, grouping)? + "\n"))?,
+        OutputFormat::Ndjson => write_stdout(&to_ndjson(&filtered, grouping)?)?,
+        OutputFormat::Csv => write_stdout(&to_csv(&filtered, grouping)?)?,
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Todo, SerdeDate, SerdeDateTime};
+    use crate::{Todo, SerdeDate, SerdeDateTime, SerdeDuration, Column, DEFAULT_COLUMNS};
 
     #[test]
     fn test_simple() {
@@ -174,15 +321,142 @@ mod tests {
                 is_priority: false,
                 notes: None,
                 recur: "".to_string(),
-                recur_until: "".to_string(),
-                prev_recur_todo_uuid: "".to_string()
+                recur_until: SerdeDate::new_empty(),
+                prev_recur_todo_uuid: "".to_string(),
+                parent_uuid: "".to_string(),
+                blocked_by: vec![],
+                time_entries: vec![],
+                estimate: SerdeDuration::new_empty(),
+                remind: SerdeDuration::new_empty(),
+                created: SerdeDateTime::new_empty()
             }
         ];
 
-        let display = disp_list(&todo, None, false);
+        let display = disp_list(&todo, None, false, &DEFAULT_COLUMNS);
         assert_eq!(display.len(), 1);
         assert_eq!(display[0].0, "All");
-        assert_eq!(display[0].1.to_string(), 
-            "\u{1b}[33m \u{1b}[39m\u{1b}[33m0\u{1b}[39m\u{1b}[33m \u{1b}[39m \u{1b}[34m \u{1b}[39m\u{1b}[34m[ ]\u{1b}[39m\u{1b}[34m \u{1b}[39m \u{1b}[33m \u{1b}[39m\u{1b}[33mSat Nov 28\u{1b}[39m\u{1b}[33m \u{1b}[39m \u{1b}[31m \u{1b}[39m\u{1b}[31mwaiting\u{1b}[39m\u{1b}[31m \u{1b}[39m \u{1b}[97m \u{1b}[39m\u{1b}[97mthis is the subject\u{1b}[39m\u{1b}[97m \u{1b}[39m");
+        assert_eq!(display[0].1.to_string(), " 0   [ ]   Sat Nov 28   waiting   this is the subject ");
+    }
+
+    #[test]
+    fn test_series_end() {
+        let due = SerdeDate::try_from(Some("nov28".to_string())).unwrap();
+        let mut todo = Todo {
+            id: 0,
+            uuid: "abcde".to_string(),
+            subject: "water plants".to_string(),
+            projects: vec![],
+            contexts: vec![],
+            due: due.clone(),
+            completed: false,
+            completed_date: SerdeDateTime::new_empty(),
+            status: "".to_string(),
+            archived: false,
+            is_priority: false,
+            notes: None,
+            recur: "weekly".to_string(),
+            recur_until: due.clone(),
+            prev_recur_todo_uuid: "".to_string(),
+            parent_uuid: "".to_string(),
+            blocked_by: vec![],
+            time_entries: vec![],
+            estimate: SerdeDuration::new_empty(),
+            remind: SerdeDuration::new_empty(),
+            created: SerdeDateTime::new_empty()
+        };
+
+        assert_eq!(subject_cell(&vec![todo.clone()], &todo), format!("water plants (last, series ends {})", due));
+
+        todo.recur_until = SerdeDate::try_from(Some("2999-01-01".to_string())).unwrap();
+        assert_eq!(subject_cell(&vec![todo.clone()], &todo), "water plants");
+    }
+
+    fn gen_todo() -> Vec<Todo> {
+        vec![
+            Todo::builder("Buy milk, eggs +groceries +town").id(0).uuid("uuid-0").notes(vec!["2%".to_string(), "\"fresh\"".to_string()]).build(),
+            Todo::builder("Write report +work").id(1).uuid("uuid-1").build(),
+        ]
+    }
+
+    #[test]
+    fn test_json_keeps_groups() {
+        let todos = gen_todo();
+
+        let json: Value = serde_json::from_str(&to_json(&todos, Some(GroupOption::Project)).unwrap()).unwrap();
+
+        let groups: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(groups, vec!["groceries", "town", "work"]);
+        let work: Vec<Todo> = serde_json::from_value(json["work"].clone()).unwrap();
+        assert_eq!(work, vec![todos[1].clone()]);
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let todos = gen_todo();
+
+        let ndjson = to_ndjson(&todos, None).unwrap();
+
+        let parsed: Vec<Todo> = ndjson.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(parsed, todos);
+
+        let grouped = to_ndjson(&todos, Some(GroupOption::Project)).unwrap();
+        assert_eq!(grouped.lines().count(), 3);
+        assert!(grouped.starts_with("{\"group\":\"groceries\",\"id\":0,"));
+    }
+
+    #[test]
+    fn test_csv() {
+        let mut todos = gen_todo();
+        todos[1].due = SerdeDate::try_from(Some("2025-01-15 15:00".to_string())).unwrap();
+        let csv = to_csv(&todos, None).unwrap();
+
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "id,uuid,subject,projects,contexts,due,due_time,completed,completed_date,status,archived,is_priority,notes,recur,recur_until,prev_recur_todo_uuid,parent_uuid,blocked_by,time_entries,estimate,remind,created");
+        assert_eq!(lines[1], "0,uuid-0,\"Buy milk, eggs +groceries +town\",groceries;town,,,,false,,,false,false,\"2%;\"\"fresh\"\"\",,,,,,,,,");
+        assert!(lines[2].contains(",2025-01-15,15:00,false,"));
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn test_columns_and_group_order() {
+        let todos = gen_todo();
+
+        let display = disp_list(&todos, Some(GroupOption::Project), false, &[Column::Subject, Column::Projects, Column::Id]);
+
+        let titles: Vec<&str> = display.iter().map(|(title, _)| *title).collect();
+        assert_eq!(titles, ["groceries", "town", "work"]);
+        assert_eq!(display[2].1.to_string(), " Write report +work   +work   1 ");
+    }
+
+    #[test]
+    fn test_nest() {
+        let todos = vec![
+            Todo::builder("compare prices").id(2).uuid("c").parent_uuid("b").build(),
+            Todo::builder("trip").id(0).uuid("a").build(),
+            Todo::builder("other").id(3).uuid("d").parent_uuid("elsewhere").build(),
+            Todo::builder("flights").id(1).uuid("b").parent_uuid("a").build(),
+        ];
+        let group: Vec<&Todo> = todos.iter().collect();
+
+        let nested: Vec<(u64, usize)> = nest(&group).into_iter().map(|(t, depth)| (t.id, depth)).collect();
+
+        assert_eq!(nested, vec![(0, 0), (1, 1), (2, 2), (3, 0)]);
+        assert_eq!(progress(&todos, &todos[1]), " 0/1");
+        assert_eq!(progress(&todos, &todos[0]), "");
+    }
+
+    #[test]
+    fn test_nest_without_uuids() {
+        let todos = [
+            Todo::builder("imported").id(0).uuid("").build(),
+            Todo::builder("also imported").id(1).uuid("").build(),
+            Todo::builder("copy").id(2).uuid("x").build(),
+            Todo::builder("copy again").id(3).uuid("x").build(),
+        ];
+        let group: Vec<&Todo> = todos.iter().collect();
+
+        let nested: Vec<(u64, usize)> = nest(&group).into_iter().map(|(t, depth)| (t.id, depth)).collect();
+
+        assert_eq!(nested, vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
     }
 }
@@ -1,40 +1,30 @@
-pub mod serde_date_time;
-pub mod serde_date;
-pub mod list;
-pub mod modify;
-pub mod notes;
-pub mod todo_files;
+#![allow(clippy::ptr_arg)]
 
 use clap::{Parser, Subcommand, ValueEnum};
-use std::fs;
 use std::error::Error;
-use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
 use std::path::PathBuf;
-use std::cmp::Ordering;
-use serde::{Deserialize, Serialize};
-use crate::serde_date_time::SerdeDateTime;
-use crate::serde_date::SerdeDate;
-use crate::list::list;
-use crate::modify::{add, edit, delete, status, complete, prioritize, archive_completed, delete_archived};
-use crate::notes::{add_note, edit_note, delete_note};
-use crate::todo_files::{init_todo, set_active, list_todos, delete_todolist, get_active_todo, nuke_all_todolists, run_git_commands};
-
-#[derive(Debug, PartialEq)]
-pub enum AppError {
-    IdNotFoundError(u64),
-    NoteNotFoundError(u64, usize),
-    NoConfigurationDirectory
-}
-impl Error for AppError {}
-impl fmt::Display for AppError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            AppError::IdNotFoundError(i) => write!(f, "ID not found: {}", i),
-            AppError::NoteNotFoundError(j, i) => write!(f, "Note number {} not found on todo number {}!", i, j),
-            AppError::NoConfigurationDirectory => write!(f, "The configuration directory has not been set up yet!"),
-        }
-    }
-}
+use std::time::Duration;
+use ul_todo_reader::{AppError, ColorChoice, Column, DEFAULT_COLUMNS, SortKey, GroupOption, FileFormat, OutputFormat, SerdeDate, SerdeDuration, Todo};
+use ul_todo_reader::serde_date::DATE_HELP;
+use ul_todo_reader::{todotxt, ical, tui};
+use ul_todo_reader::list::list;
+use ul_todo_reader::filter::Filter;
+use ul_todo_reader::selection::select;
+use ul_todo_reader::timer::{start, stop, running, parse_since, print_time_report};
+use ul_todo_reader::remind::{run_daemon, remind_once};
+use ul_todo_reader::server::serve;
+use ul_todo_reader::sync::sync;
+use ul_todo_reader::agenda::{print_agenda, print_cal};
+use chrono::Local;
+use itertools::Itertools;
+use ul_todo_reader::modify::{add, edit, delete, status, complete, prioritize, archive, archive_completed, delete_archived, describe_todo, set_parent, edit_parent, open_subtasks, block, unblock, next_actions, set_estimate, set_remind, plan};
+use ul_todo_reader::notes::{add_note, edit_note, delete_note};
+use ul_todo_reader::journal::history;
+use ul_todo_reader::todo_files::{init_todo, set_active, list_todos, delete_todolist, get_active_todo, nuke_all_todolists, run_git_commands, Transaction, todolist_name, auto_commit_enabled, set_auto_commit, sync_endpoint, set_sync_endpoint, get_config, get_config_unchecked};
+use ul_todo_reader::config::{Source, current};
+use ul_todo_reader::theme::{self, Theme, use_color};
 
 #[derive(Subcommand, Debug)]
 enum Command {
@@ -45,31 +35,56 @@ enum Command {
         group: Option<GroupOption>,
         #[arg(short, long, default_value_t=false)]
         notes: bool,
+        #[arg(short, long, value_enum, default_value_t=OutputFormat::Table)]
+        output: OutputFormat,
+        #[arg(long, value_enum, value_delimiter = ',', help = "Table columns, like id,due,subject")]
+        columns: Vec<Column>,
+        #[arg(long, value_delimiter = ',', allow_hyphen_values = true, help = "Sort by these fields in turn, like due,-priority; - sorts descending. Fields: due, priority, subject, id, created, status")]
+        sort: Vec<SortKey>,
+        filter: Vec<String>,
     },
     #[clap(alias("a"))]
     Add {
-        #[arg(short, long)]
+        #[arg(short, long, help = "Due date, see --help for the formats", long_help = DATE_HELP)]
         due: Option<String>,
         #[arg(short, long)]
         recur: Option<String>,
+        #[arg(short, long, help = "Last date a recurring todo repeats", long_help = DATE_HELP)]
+        until: Option<String>,
+        #[arg(short, long)]
+        parent: Option<u64>,
+        #[arg(short, long)]
+        estimate: Option<String>,
+        #[arg(long, help = "Notify this long before the due date, like 1h or 1d")]
+        remind: Option<String>,
         subject: Vec<String>,
     },
     #[clap(alias("e"))]
     Edit {
         id: u64,
-        #[arg(short, long)]
+        #[arg(short, long, help = "Due date, see --help for the formats", long_help = DATE_HELP)]
         due: Option<String>,
         #[arg(short, long)]
         recur: Option<String>,
+        #[arg(short, long, help = "Last date a recurring todo repeats", long_help = DATE_HELP)]
+        until: Option<String>,
+        #[arg(short, long, help = "Id of the parent todo, or none to make it a top-level todo")]
+        parent: Option<String>,
+        #[arg(short, long)]
+        estimate: Option<String>,
+        #[arg(long, help = "Notify this long before the due date, like 1h or 1d")]
+        remind: Option<String>,
         subject: Vec<String>,
     },
     #[clap(alias("d"))]
     Delete {
-        id: u64
+        #[arg(required = true)]
+        selection: Vec<String>
     },
     #[clap(alias("s"))]
     Status {
-        id: u64,
+        #[arg(required = true, num_args = 1..)]
+        selection: Vec<String>,
         stat: String
     },
     #[clap(alias("an"))]
@@ -90,20 +105,59 @@ enum Command {
     },
     #[clap(alias("c"))]
     Complete {
-        id: u64
+        #[arg(long, default_value_t=false)]
+        children: bool,
+        #[arg(required = true)]
+        selection: Vec<String>
     },
     #[clap(alias("uc"))]
     Uncomplete {
-        id: u64
+        #[arg(required = true)]
+        selection: Vec<String>
     },
     #[clap(alias("p"))]
     Prioritize {
-        id: u64
+        #[arg(required = true)]
+        selection: Vec<String>
     },
     #[clap(alias("up"))]
     Unprioritize {
+        #[arg(required = true)]
+        selection: Vec<String>
+    },
+    Block {
+        id: u64,
+        #[arg(long)]
+        on: u64,
+    },
+    Unblock {
+        id: u64,
+        #[arg(long)]
+        on: Option<u64>,
+    },
+    #[clap(alias("n"))]
+    Next {
+        #[arg(short, long, default_value_t=false)]
+        notes: bool,
+        #[arg(short, long, value_enum, default_value_t=OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    Plan {
+        #[arg(short, long)]
+        capacity: String,
+        #[arg(short, long, default_value_t=false)]
+        notes: bool,
+        #[arg(short, long, value_enum, default_value_t=OutputFormat::Table)]
+        output: OutputFormat,
+    },
+    Start {
         id: u64
     },
+    Stop { },
+    Report {
+        #[command(subcommand)]
+        report: Report
+    },
     #[clap(alias("lt"))]
     ListTodolists {},
     #[clap(alias("it"))]
@@ -121,19 +175,99 @@ enum Command {
     NukeAllTodolists {
     },
     Git {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         commands: Vec<String>
     },
+    AutoCommit {
+        state: Option<Toggle>
+    },
     #[clap(alias("ar"))]
-    Archive { },
+    Archive {
+        selection: Vec<String>
+    },
     #[clap(alias("gc"))]
     GarbageCollection { },
+    Export {
+        #[arg(short, long)]
+        format: FileFormat,
+    },
+    Import {
+        #[arg(short, long)]
+        format: FileFormat,
+        file: PathBuf,
+    },
+    #[clap(alias("u"))]
+    Undo {
+        #[arg(default_value_t=1)]
+        n: usize
+    },
+    #[clap(alias("r"))]
+    Redo {
+        #[arg(default_value_t=1)]
+        n: usize
+    },
+    #[clap(alias("h"))]
+    History { },
+    Tui { },
+    Remind {
+        #[arg(long, default_value_t=false, help = "Keep running and send desktop notifications")]
+        daemon: bool,
+    },
+    Serve {
+        #[arg(short, long, default_value_t=7373)]
+        port: u16,
+    },
+    Sync {
+        #[arg(long, help = "URL to sync with; remembered for the todolist")]
+        endpoint: Option<String>,
+    },
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    Agenda {
+        #[arg(short, long, default_value_t=14, help = "How many days ahead to show, starting today")]
+        days: u64,
+    },
+    Cal {
+        #[arg(help = "Month to show, like 2025-03, 3 or march; defaults to this month")]
+        month: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    Get {
+        key: String,
+        #[arg(short, long, help = "Look up the value for this todolist")]
+        todolist: Option<String>,
+    },
+    Set {
+        key: String,
+        value: String,
+        #[arg(short, long, help = "Only set the value for this todolist")]
+        todolist: Option<String>,
+    },
+    List {
+        #[arg(short, long, help = "Show the values for this todolist")]
+        todolist: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum Report {
+    Time {
+        #[arg(long, help = "Only count time from this date on", long_help = DATE_HELP)]
+        since: Option<String>,
+        #[arg(short, long)]
+        group: Option<GroupOption>,
+    },
 }
 
 #[derive(ValueEnum, Clone, Copy, Debug)]
-pub enum GroupOption {
-    Project,
-    Context,
-    Status
+enum Toggle {
+    On,
+    Off
 }
 
 #[derive(Parser, Debug)]
@@ -141,37 +275,59 @@ pub enum GroupOption {
 struct Args {
     #[command(subcommand)]
     command: Command,
+    #[arg(long, global = true, value_enum, help = "Color the output; auto colors unless NO_COLOR is set or the output is not a terminal")]
+    color: Option<ColorChoice>,
+}
+
+/// Asks a yes/no question, answering no when stdin is not a terminal.
+fn confirm(question: &str) -> Result<bool, Box<dyn Error>> {
+    if !io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
-pub struct Todo {
-    id: u64,
-    uuid: String,
-    subject: String,
-    projects: Vec<String>,
-    contexts: Vec<String>,
-    due: SerdeDate,
-    completed: bool, 
-    completed_date: SerdeDateTime,
-    status: String,
-    archived: bool, 
-    is_priority: bool,
-    notes: Option<Vec<String>>,
-    recur: String,
-    recur_until: String,
-    prev_recur_todo_uuid: String,
+/// `None` when the option was not given, so that "none" can clear a date.
+fn parse_date(date: Option<String>) -> Result<Option<SerdeDate>, AppError> {
+    date.map(|d| SerdeDate::try_from(Some(d))).transpose()
 }
 
-fn sort_todo_list(list: &mut Vec<Todo>) {
-    list.sort_by(|a, b| {
-        if !((!a.is_priority).cmp(&(!b.is_priority)) == Ordering::Equal) {
-            return (!a.is_priority).cmp(&(!b.is_priority)); //priority tasks at the top (lesser)
-        }
-        if !a.due.is_some() && !b.due.is_some() {
-            return a.subject.cmp(&b.subject);
-        }
-        a.due.cmp(&b.due)
-    });
+fn describe_selection(todos: &Vec<Todo>, selection: &[String]) -> String {
+    match select(todos, selection).as_deref() {
+        Ok([id]) => describe_todo(todos, *id),
+        Ok(ids) if !ids.is_empty() => ids.iter().map(|id| format!("#{}", id)).join(", "),
+        _ => selection.join(" "),
+    }
+}
+
+fn describe(command: &Command, todos: &Vec<Todo>) -> Option<String> {
+    Some(match command {
+        Command::Add { subject: s, .. } => format!("add: {}", s.join(" ")),
+        Command::Edit { id: i, .. } => format!("edit {}", describe_todo(todos, *i)),
+        Command::Delete { selection: s } => format!("delete {}", describe_selection(todos, s)),
+        Command::Status { selection: s, stat: st } => format!("set status \"{}\" on {}", st, describe_selection(todos, s)),
+        Command::AddNote { id: i, .. } => format!("add note to {}", describe_todo(todos, *i)),
+        Command::EditNote { id: i, index: x, .. } => format!("edit note {} on {}", x, describe_todo(todos, *i)),
+        Command::DeleteNote { id: i, index: x } => format!("delete note {} on {}", x, describe_todo(todos, *i)),
+        Command::Complete { selection: s, .. } => format!("complete {}", describe_selection(todos, s)),
+        Command::Uncomplete { selection: s } => format!("uncomplete {}", describe_selection(todos, s)),
+        Command::Prioritize { selection: s } => format!("prioritize {}", describe_selection(todos, s)),
+        Command::Unprioritize { selection: s } => format!("unprioritize {}", describe_selection(todos, s)),
+        Command::Block { id: i, on: o } => format!("block {} on {}", describe_todo(todos, *i), describe_todo(todos, *o)),
+        Command::Unblock { id: i, on: Some(o) } => format!("unblock {} from {}", describe_todo(todos, *i), describe_todo(todos, *o)),
+        Command::Unblock { id: i, on: None } => format!("unblock {}", describe_todo(todos, *i)),
+        Command::Start { id: i } => format!("start timer on {}", describe_todo(todos, *i)),
+        Command::Stop {  } => format!("stop timer on {}", running(todos).map(|i| describe_todo(todos, i)).unwrap_or_default()),
+        Command::Archive { selection: s } if s.is_empty() => "archive completed todos".to_string(),
+        Command::Archive { selection: s } => format!("archive {}", describe_selection(todos, s)),
+        Command::GarbageCollection {  } => "delete archived todos".to_string(),
+        Command::Import { file: p, .. } => format!("import {}", p.display()),
+        _ => return None,
+    })
 }
 
 #[cfg(feature="dbg")] 
@@ -179,16 +335,34 @@ fn todos_name() -> Result<PathBuf, Box<dyn Error>> {
     // const TODOS_FILENAME: &str = "/home/tortus/.todos.json";
     const TODOS_FILENAME: &str = "output.json";
 
-    Ok(PathBuf::new(TODOS_FILENAME))
+    Ok(PathBuf::from(TODOS_FILENAME))
 }
 
 #[cfg(not(feature="dbg"))]
 fn todos_name() -> Result<PathBuf, Box<dyn Error>> {
-    Ok(get_active_todo()?)
+    get_active_todo()
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
+    let config = match get_config() {
+        Ok(config) => config,
+        // `tlist config` must still work to fix a bad config
+        Err(e) if matches!(args.command, Command::Config { .. }) => {
+            eprintln!("{}", e);
+            get_config_unchecked()?
+        },
+        Err(e) => return Err(e),
+    };
+    let active = todos_name().ok().and_then(|p| todolist_name(&p).ok());
+    let settings = config.settings(active.as_deref());
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    colored::control::set_override(use_color(args.color.unwrap_or(settings.color), no_color, io::stdout().is_terminal()));
+    theme::init(Theme::load(config.dir(), &settings.theme).unwrap_or_else(|e| {
+        eprintln!("Could not load theme \"{}\", using the default: {}", settings.theme, e);
+        Theme::default()
+    }));
+    ul_todo_reader::config::init(settings);
 
     // println!("{:?}", r);
     match args.command {
@@ -197,37 +371,192 @@ fn main() -> Result<(), Box<dyn Error>> {
         Command::SetTodolist { name: n } => set_active(&n)?,
         Command::DeleteTodolist { name: n } => delete_todolist(&n)?,
         Command::NukeAllTodolists {} => nuke_all_todolists()?,
+        Command::Tui {} => tui::run(&todos_name()?)?,
+        Command::Remind { daemon: true } => run_daemon(Duration::from_secs(30))?,
+        Command::Remind { daemon: false } => remind_once()?,
+        Command::Serve { port: p } => serve(p)?,
+        Command::Config { action: ConfigAction::Get { key: k, todolist: t } } => println!("{}", config.get(&k, t.as_deref())?.0),
+        Command::Config { action: ConfigAction::Set { key: k, value: v, todolist: t } } => {
+            let mut config = config;
+            config.set(&k, &v, t.as_deref())?;
+            config.save()?;
+        },
+        Command::Config { action: ConfigAction::List { todolist: t } } => {
+            for (key, value, source) in config.list(t.as_deref()) {
+                let from = match source {
+                    Source::Default => " (default)",
+                    Source::Global => "",
+                    Source::Todolist => " (todolist)",
+                };
+                println!("{} = {:?}{}", key, value, from);
+            }
+        },
+        Command::Sync { endpoint: e } => {
+            let path = todos_name()?;
+            let name = todolist_name(&path)?;
+            if let Some(e) = e {
+                set_sync_endpoint(&name, &e)?;
+            }
+            let endpoint = sync_endpoint(&name)?.ok_or(AppError::NoSyncEndpointError(name))?;
+            let merge = sync(&path, &endpoint)?;
+            println!("Pulled {} and pushed {} changes.", merge.pulled, merge.pushed);
+            for conflict in &merge.conflicts {
+                println!("Conflict: {}", conflict);
+            }
+        },
         Command::Git { commands: c } => print!("{}", run_git_commands(&c)?),
+        Command::AutoCommit { state: t } => {
+            let name = todolist_name(&todos_name()?)?;
+            if let Some(t) = t {
+                set_auto_commit(&name, matches!(t, Toggle::On))?;
+            }
+            let state = if auto_commit_enabled(&name)? { "on" } else { "off" };
+            println!("Automatic git commits are {} for {}.", state, name);
+        },
         c => { 
 
     let todos_file = todos_name()?;
-    let todos_raw = fs::read_to_string(&todos_file)?;
-    let mut r: Vec<Todo> = serde_json::from_str(&todos_raw)?;
+    let mut tx = Transaction::open(&todos_file)?;
+    let mut operation = describe(&c, &tx.todos);
+    let journaled = operation.is_some();
     match c {
-        Command::List { group: a, notes: b } => list(&r, a, b),
-        Command::Add { due: d, recur: rc, subject: s } => add(&mut r, s.join(" "), SerdeDate::try_from(d)?, rc),
-        Command::Edit { id: i, due: d, recur: rc, subject: s } => edit(&mut r, i, s.join(" "), SerdeDate::try_from(d)?, rc)?,
-        Command::Delete { id: i } => delete(&mut r, i)?,
-        Command::Status { id: i, stat: s } => status(&mut r, i, s)?,
-        Command::AddNote { id: i, note: n } => add_note(&mut r, i, n)?,
-        Command::EditNote { id: i, index: x, note: n } => edit_note(&mut r, i, x, n)?,
-        Command::DeleteNote { id: i, index: x } => delete_note(&mut r, i, x)?,
-        Command::Complete { id: i } => complete(&mut r, i, true)?,
-        Command::Uncomplete { id: i } => complete(&mut r, i, false)?,
-        Command::Prioritize { id: i } => prioritize(&mut r, i, true)?,
-        Command::Unprioritize { id: i } => prioritize(&mut r, i, false)?,
-        Command::Archive {  } => archive_completed(&mut r),
-        Command::GarbageCollection {  } => delete_archived(&mut r),
+        Command::List { group: a, notes: b, output: o, columns: c, sort: s, filter: f } => {
+            let columns = if c.is_empty() { DEFAULT_COLUMNS.to_vec() } else { c };
+            list(&tx.todos, a.or(current().group), b, &Filter::parse(&f)?, o, &columns, &s)?
+        },
+        Command::Add { due: d, recur: rc, until: u, parent: p, estimate: e, remind: r, subject: s } => {
+            let id = add(&mut tx.todos, s.join(" "), SerdeDate::try_from(d)?, rc, SerdeDate::try_from(u)?)?;
+            if let Some(e) = e {
+                set_estimate(&mut tx.todos, id, SerdeDuration::try_from(e.as_str())?)?;
+            }
+            if let Some(r) = r {
+                set_remind(&mut tx.todos, id, SerdeDuration::try_from(r.as_str())?)?;
+            }
+            if let Some(p) = p {
+                set_parent(&mut tx.todos, id, p)?;
+            }
+            println!("Todo {} added.", id);
+        },
+        Command::Edit { id: i, due: d, recur: rc, until: u, parent: p, estimate: e, remind: r, subject: s } => {
+            edit(&mut tx.todos, i, s.join(" "), parse_date(d)?, rc, parse_date(u)?)?;
+            if let Some(p) = p {
+                edit_parent(&mut tx.todos, i, &p)?;
+            }
+            if let Some(e) = e {
+                set_estimate(&mut tx.todos, i, SerdeDuration::try_from(e.as_str())?)?;
+            }
+            if let Some(r) = r {
+                set_remind(&mut tx.todos, i, SerdeDuration::try_from(r.as_str())?)?;
+            }
+        },
+        Command::Delete { selection: s } => {
+            for i in select(&tx.todos, &s)? {
+                delete(&mut tx.todos, i)?;
+            }
+        },
+        Command::Status { selection: s, stat: st } => {
+            for i in select(&tx.todos, &s)? {
+                status(&mut tx.todos, i, st.clone())?;
+            }
+        },
+        Command::AddNote { id: i, note: n } => add_note(&mut tx.todos, i, n)?,
+        Command::EditNote { id: i, index: x, note: n } => edit_note(&mut tx.todos, i, x, n)?,
+        Command::DeleteNote { id: i, index: x } => delete_note(&mut tx.todos, i, x)?,
+        Command::Complete { children: c, selection: s } => {
+            for i in select(&tx.todos, &s)? {
+                let mut ids = vec![i];
+                let subtasks = open_subtasks(&tx.todos, i)?;
+                if !subtasks.is_empty() && (c || confirm(&format!("Todo {} has {} open subtasks. Complete them too?", i, subtasks.len()))?) {
+                    ids.extend(subtasks);
+                }
+                for i in ids {
+                    if let Some(next) = complete(&mut tx.todos, i, true)? {
+                        println!("Todo {} added, due {}.", next.id(), next.due());
+                    }
+                }
+            }
+        },
+        Command::Uncomplete { selection: s } => {
+            for i in select(&tx.todos, &s)? {
+                complete(&mut tx.todos, i, false)?;
+            }
+        },
+        Command::Prioritize { selection: s } => {
+            for i in select(&tx.todos, &s)? {
+                prioritize(&mut tx.todos, i, true)?;
+            }
+        },
+        Command::Unprioritize { selection: s } => {
+            for i in select(&tx.todos, &s)? {
+                prioritize(&mut tx.todos, i, false)?;
+            }
+        },
+        Command::Block { id: i, on: o } => block(&mut tx.todos, i, o)?,
+        Command::Unblock { id: i, on: o } => unblock(&mut tx.todos, i, o)?,
+        Command::Agenda { days: d } => print_agenda(&tx.todos, d)?,
+        Command::Cal { month: m } => print_cal(&tx.todos, m.as_deref())?,
+        Command::Next { notes: b, output: o } => list(&next_actions(&tx.todos), None, b, &Filter::parse(&[])?, o, &DEFAULT_COLUMNS, &[])?,
+        Command::Plan { capacity: c, notes: b, output: o } => {
+            let capacity = SerdeDuration::try_from(c.as_str())?;
+            let planned = plan(&tx.todos, capacity);
+            list(&planned, None, b, &Filter::parse(&[])?, o, &DEFAULT_COLUMNS, &[])?;
+            if matches!(o, OutputFormat::Table) {
+                let total: SerdeDuration = planned.iter().map(|t| *t.estimate()).sum();
+                println!("Planned {} of {}.", if total.is_some() { total } else { SerdeDuration::from_minutes(0) }, capacity);
+            }
+        },
+        Command::Start { id: i } => start(&mut tx.todos, i, Local::now())?,
+        Command::Stop {  } => {
+            let (id, length) = stop(&mut tx.todos, Local::now())?;
+            println!("Stopped todo {} after {}h {:02}m.", id, length.num_hours(), length.num_minutes() % 60);
+        },
+        Command::Report { report: Report::Time { since: s, group: g } } => {
+            let since = s.map(|s| parse_since(&s, Local::now().date_naive())).transpose()?;
+            let groupings = match g {
+                Some(g) => vec![g],
+                None => vec![GroupOption::Project, GroupOption::Context],
+            };
+            print_time_report(&tx.todos, &groupings, since);
+        },
+        Command::Archive { selection: s } if s.is_empty() => archive_completed(&mut tx.todos),
+        Command::Archive { selection: s } => {
+            for i in select(&tx.todos, &s)? {
+                archive(&mut tx.todos, i)?;
+            }
+        },
+        Command::GarbageCollection {  } => delete_archived(&mut tx.todos),
+        Command::Export { format: FileFormat::Todotxt } => print!("{}", todotxt::export(&tx.todos)?),
+        Command::Export { format: FileFormat::Ical } => print!("{}", ical::export(&tx.todos)?),
+        Command::Import { format: FileFormat::Todotxt, file: p } => {
+            let count = todotxt::import(&mut tx.todos, &fs::read_to_string(p)?)?;
+            println!("Imported {} todos.", count);
+        },
+        Command::Import { format: FileFormat::Ical, file: p } => {
+            let (added, updated) = ical::import(&mut tx.todos, &fs::read_to_string(p)?)?;
+            println!("Added {} and updated {} todos.", added, updated);
+        },
+        Command::Undo { n } => {
+            let undone = tx.journal.undo(&mut tx.todos, n)?;
+            undone.iter().for_each(|op| println!("Undid {}", op));
+            operation = if undone.is_empty() { None } else { Some(format!("undo {}", undone.join("; "))) };
+        },
+        Command::Redo { n } => {
+            let redone = tx.journal.redo(&mut tx.todos, n)?;
+            redone.iter().for_each(|op| println!("Redid {}", op));
+            operation = if redone.is_empty() { None } else { Some(format!("redo {}", redone.join("; "))) };
+        },
+        Command::History {  } => history(&tx.journal),
         _ => unreachable!(),
     }
 
-    sort_todo_list(&mut r);
-    let redone = serde_json::to_string(&r)?;
-    fs::write(todos_file, redone)?;
+    if journaled {
+        tx.commit(operation.as_deref())?;
+    } else {
+        tx.commit_unrecorded(operation.as_deref())?;
+    }
 
         }
     }
-    
 
     Ok(())
 }
@@ -1,14 +1,17 @@
 use uuid::Uuid;
+use chrono::{Local, NaiveDate};
 use crate::serde_date_time::SerdeDateTime;
 use crate::serde_date::SerdeDate;
-use crate::Todo;
-use crate::AppError;
+use crate::serde_duration::SerdeDuration;
+use crate::recur::{Recurrence, normalize_recur};
+use crate::{Todo, sort_todo_list};
+use crate::{AppError, config, timer};
 
-fn get_contexts_and_projects(sub: &str) -> (Vec<String>, Vec<String>) {
+pub(crate) fn get_contexts_and_projects(sub: &str) -> (Vec<String>, Vec<String>) {
     let mut ctx = Vec::new();
     let mut projs = Vec::new();
     sub.split_whitespace().for_each(|word: &str| {
-        match word.chars().nth(0) {
+        match word.chars().next() {
             Some('+') => {
                 let chs: String = word.chars().skip(1).collect();
                 if !chs.is_empty() {
@@ -27,9 +30,9 @@ fn get_contexts_and_projects(sub: &str) -> (Vec<String>, Vec<String>) {
     (ctx, projs)
 }
 
-fn find_new_id(todos: &Vec<Todo>) -> u64 {
+pub(crate) fn find_new_id(todos: &Vec<Todo>) -> u64 {
     let mut found: Vec<bool> = vec![false; todos.len()];
-    todos.into_iter().for_each(|td| {
+    todos.iter().for_each(|td| {
         if (td.id as usize) < found.len() {
             found[(td.id) as usize] = true;
         }
@@ -42,29 +45,21 @@ fn find_new_id(todos: &Vec<Todo>) -> u64 {
     }) as u64
 }
 
-pub fn add(todos: &mut Vec<Todo>, sub: String, due: SerdeDate, recur: Option<String>) {
-    let (ctx, projs) = get_contexts_and_projects(&sub);
-    let uuid = Uuid::new_v4();
+pub fn add(todos: &mut Vec<Todo>, sub: String, due: SerdeDate, recur: Option<String>, until: SerdeDate) -> Result<u64, AppError> {
+    let recur = recur.map(|r| normalize_recur(&r)).transpose()?.unwrap_or_default();
+    if recur.is_empty() && until.is_some() {
+        return Err(AppError::UntilWithoutRecurrenceError);
+    }
     let id = find_new_id(todos);
-    let todo_to_add = Todo {
-        id: id,
-        uuid: uuid.to_string(),
-        subject: sub,
-        projects: projs,
-        contexts: ctx,
-        due: due,
-        completed: false,
-        completed_date: SerdeDateTime::new_empty(),
-        status: "".to_string(),
-        archived: false,
-        is_priority: false,
-        notes: None,
-        recur: recur.unwrap_or("".to_string()),
-        recur_until: "".to_string(),
-        prev_recur_todo_uuid: "".to_string(),
-    };
+    let todo_to_add = Todo::builder(&sub)
+        .id(id)
+        .due(due)
+        .recur(&recur)?
+        .recur_until(until)
+        .created(SerdeDateTime::now())
+        .build();
     todos.push(todo_to_add);
-    println!("Todo {} added.", id);
+    Ok(id)
 }
 
 pub fn find_todo_index(todos: &Vec<Todo>, id: u64) -> Result<usize, AppError> {
@@ -74,6 +69,14 @@ pub fn find_todo_index(todos: &Vec<Todo>, id: u64) -> Result<usize, AppError> {
     Err(AppError::IdNotFoundError(id))
 }
 
+/// "#id: subject", for messages about a todo that may no longer exist.
+pub fn describe_todo(todos: &Vec<Todo>, id: u64) -> String {
+    match todos.iter().find(|t| t.id == id) {
+        Some(todo) => format!("#{}: {}", id, todo.subject),
+        None => format!("#{}", id),
+    }
+}
+
 pub fn find_todo_mut(todos: &mut Vec<Todo>, id: u64) -> Result<&mut Todo, AppError> {
     if let Some(todo) = todos.iter_mut().find(|t| t.id == id) {
         return Ok(todo);
@@ -81,42 +84,271 @@ pub fn find_todo_mut(todos: &mut Vec<Todo>, id: u64) -> Result<&mut Todo, AppErr
     Err(AppError::IdNotFoundError(id))
 }
 
-pub fn edit(todos: &mut Vec<Todo>, id: u64, sub: String, due: SerdeDate, recur: Option<String>) -> Result<(), AppError> {
+/// An empty subject or `None` leaves that part as it is; an empty date
+/// clears it. Clearing the recurrence clears its end date too.
+pub fn edit(todos: &mut Vec<Todo>, id: u64, sub: String, due: Option<SerdeDate>, recur: Option<String>, until: Option<SerdeDate>) -> Result<(), AppError> {
+    let recur = recur.map(|r| normalize_recur(&r)).transpose()?;
     let todo: &mut Todo = find_todo_mut(todos, id)?;
-    if due.is_some() {
+    let recurs = !recur.as_ref().unwrap_or(&todo.recur).is_empty();
+    if !recurs && until.as_ref().is_some_and(|u| u.is_some()) {
+        return Err(AppError::UntilWithoutRecurrenceError);
+    }
+    if let Some(due) = due {
         todo.due = due;
     }
     if let Some(recurrance) = recur {
         todo.recur = recurrance;
     }
-    todo.subject = sub;
+    if let Some(until) = until {
+        todo.recur_until = until;
+    }
+    if !recurs {
+        todo.recur_until = SerdeDate::new_empty();
+    }
+    if !sub.is_empty() {
+        todo.subject = sub;
+    }
     Ok(())
 }
 
+/// The uuids of a todo's subtasks at any depth.
+pub fn descendants(todos: &Vec<Todo>, uuid: &str) -> Vec<String> {
+    let mut found: Vec<String> = Vec::new();
+    let mut queue = vec![uuid.to_string()];
+    while let Some(parent) = queue.pop() {
+        for child in todos.iter().filter(|t| t.parent_uuid == parent) {
+            if child.uuid != uuid && !found.contains(&child.uuid) {
+                found.push(child.uuid.clone());
+                queue.push(child.uuid.clone());
+            }
+        }
+    }
+    found
+}
+
+/// Makes `id` a subtask of `parent`, refusing to create a cycle.
+pub fn set_parent(todos: &mut Vec<Todo>, id: u64, parent: u64) -> Result<(), AppError> {
+    let parent_uuid = todos[find_todo_index(todos, parent)?].uuid.clone();
+    let i: usize = find_todo_index(todos, id)?;
+    if id == parent || parent_uuid.is_empty() || descendants(todos, &todos[i].uuid).contains(&parent_uuid) {
+        return Err(AppError::InvalidParentError(id, parent));
+    }
+    todos[i].parent_uuid = parent_uuid;
+    Ok(())
+}
+
+/// Sets the parent from `--parent`: an id, or "none" to move the todo to
+/// the top level.
+pub fn edit_parent(todos: &mut Vec<Todo>, id: u64, parent: &str) -> Result<(), AppError> {
+    if parent.trim().eq_ignore_ascii_case("none") {
+        find_todo_mut(todos, id)?.parent_uuid = String::new();
+        return Ok(());
+    }
+    let parent = parent.trim().parse().map_err(|_| AppError::InvalidSelectionError(parent.to_string()))?;
+    set_parent(todos, id, parent)
+}
+
+/// The ids of the subtasks of `id`, at any depth, that are not completed.
+pub fn open_subtasks(todos: &Vec<Todo>, id: u64) -> Result<Vec<u64>, AppError> {
+    let uuid = &todos[find_todo_index(todos, id)?].uuid;
+    let subtasks = descendants(todos, uuid);
+    Ok(todos.iter().filter(|t| !t.completed && subtasks.contains(&t.uuid)).map(|t| t.id).collect())
+}
+
+/// Whether `from` waits on `to`, directly or through other todos.
+fn depends_on(todos: &Vec<Todo>, from: &str, to: &str) -> bool {
+    let mut seen: Vec<&str> = Vec::new();
+    let mut queue = vec![from];
+    while let Some(uuid) = queue.pop() {
+        if uuid == to {
+            return true;
+        }
+        if seen.contains(&uuid) {
+            continue;
+        }
+        seen.push(uuid);
+        if let Some(todo) = todos.iter().find(|t| t.uuid == uuid) {
+            queue.extend(todo.blocked_by.iter().map(|b| b.as_str()));
+        }
+    }
+    false
+}
+
+/// Marks `id` as blocked until `on` is completed. The link is kept by
+/// uuid, so it survives `on`'s id being reused.
+pub fn block(todos: &mut Vec<Todo>, id: u64, on: u64) -> Result<(), AppError> {
+    let on_uuid = todos[find_todo_index(todos, on)?].uuid.clone();
+    let i: usize = find_todo_index(todos, id)?;
+    if depends_on(todos, &on_uuid, &todos[i].uuid) {
+        return Err(AppError::DependencyCycleError(id, on));
+    }
+    if !todos[i].blocked_by.contains(&on_uuid) {
+        todos[i].blocked_by.push(on_uuid);
+    }
+    Ok(())
+}
+
+/// Removes the link to `on`, or every link when `on` is `None`. Fails if
+/// `id` does not wait on `on`.
+pub fn unblock(todos: &mut Vec<Todo>, id: u64, on: Option<u64>) -> Result<(), AppError> {
+    let on_uuid = on.map(|on| find_todo_index(todos, on).map(|i| todos[i].uuid.clone())).transpose()?;
+    let todo: &mut Todo = find_todo_mut(todos, id)?;
+    match (on, on_uuid) {
+        (Some(on), Some(uuid)) if !todo.blocked_by.contains(&uuid) => return Err(AppError::NotBlockedError(id, on)),
+        (_, Some(uuid)) => todo.blocked_by.retain(|b| *b != uuid),
+        (_, None) => todo.blocked_by.clear(),
+    }
+    Ok(())
+}
+
+/// Drops links to todos that are gone, so that none is left waiting on a
+/// todo it can no longer be unblocked from.
+fn unlink_blockers(todos: &mut Vec<Todo>, gone: &[String]) {
+    todos.iter_mut().for_each(|t| t.blocked_by.retain(|b| !gone.contains(b)));
+}
+
+/// A todo is blocked while any todo it waits on still exists and is not
+/// completed.
+pub fn is_blocked(todos: &Vec<Todo>, todo: &Todo) -> bool {
+    todo.blocked_by.iter().any(|uuid| todos.iter().any(|t| t.uuid == *uuid && !t.completed))
+}
+
+/// The todos that can be worked on now: unblocked, incomplete and
+/// unarchived, in `sort_todo_list` order.
+pub fn next_actions(todos: &Vec<Todo>) -> Vec<Todo> {
+    let mut next: Vec<Todo> = todos.iter()
+        .filter(|t| !t.completed && !t.archived && !is_blocked(todos, t))
+        .cloned()
+        .collect();
+    sort_todo_list(&mut next);
+    next
+}
+
+pub fn set_estimate(todos: &mut Vec<Todo>, id: u64, estimate: SerdeDuration) -> Result<(), AppError> {
+    let todo: &mut Todo = find_todo_mut(todos, id)?;
+    todo.estimate = estimate;
+    Ok(())
+}
+
+/// How long before its due date a todo should be reminded of; empty for no
+/// reminder.
+pub fn set_remind(todos: &mut Vec<Todo>, id: u64, remind: SerdeDuration) -> Result<(), AppError> {
+    let todo: &mut Todo = find_todo_mut(todos, id)?;
+    todo.remind = remind;
+    Ok(())
+}
+
+/// Picks next actions in `sort_todo_list` order, skipping any whose
+/// estimate no longer fits, until `capacity` is used up. Todos without an
+/// estimate cannot be planned and are left out.
+pub fn plan(todos: &Vec<Todo>, capacity: SerdeDuration) -> Vec<Todo> {
+    let mut left = capacity.minutes().unwrap_or(0);
+    next_actions(todos).into_iter().filter(|t| {
+        match t.estimate.minutes() {
+            Some(m) if m <= left => {
+                left -= m;
+                true
+            },
+            _ => false,
+        }
+    }).collect()
+}
+
+/// Moves the children of removed todos up to the closest remaining ancestor,
+/// or to the top level if the removed parents form a cycle.
+fn reparent_orphans(todos: &mut Vec<Todo>, removed: &[Todo]) {
+    for todo in todos.iter_mut() {
+        let mut visited: Vec<&str> = Vec::new();
+        while let Some(parent) = removed.iter().find(|r| r.uuid == todo.parent_uuid && !r.uuid.is_empty()) {
+            if visited.contains(&parent.uuid.as_str()) {
+                todo.parent_uuid = String::new();
+                break;
+            }
+            visited.push(&parent.uuid);
+            todo.parent_uuid = parent.parent_uuid.clone();
+        }
+    }
+}
+
 pub fn delete(todos: &mut Vec<Todo>, id: u64) -> Result<(), AppError> {
     let i: usize = find_todo_index(todos, id)?;
-    todos.remove(i);
-    return Ok(());
+    let removed = todos.remove(i);
+    unlink_blockers(todos, std::slice::from_ref(&removed.uuid));
+    reparent_orphans(todos, &[removed]);
+    Ok(())
 }
 
 pub fn status(todos: &mut Vec<Todo>, id: u64, stat: String) -> Result<(), AppError> {
     let todo: &mut Todo = find_todo_mut(todos, id)?;
     todo.status = stat;
-    return Ok(());
+    Ok(())
 }
 
-const COMPLETED_STATUS: &str = "completed";
-pub fn complete(todos: &mut Vec<Todo>, id: u64, set: bool) -> Result<(), AppError> {
-    let todo: &mut Todo = find_todo_mut(todos, id)?;
+/// The due date of the earliest occurrence of `todo`'s series still in the
+/// list, following `prev_recur_todo_uuid` back from `todo` itself.
+pub fn series_anchor(todos: &Vec<Todo>, todo: &Todo) -> Option<NaiveDate> {
+    let mut first = todo;
+    let mut seen: Vec<&str> = vec![&todo.uuid];
+    while let Some(prev) = todos.iter().find(|t| !t.uuid.is_empty() && t.uuid == first.prev_recur_todo_uuid) {
+        if seen.contains(&prev.uuid.as_str()) {
+            break;
+        }
+        seen.push(&prev.uuid);
+        first = prev;
+    }
+    first.due.date()
+}
+
+fn next_occurrence(todos: &Vec<Todo>, todo: &Todo, today: NaiveDate) -> Result<Option<Todo>, AppError> {
+    if todo.recur.is_empty() {
+        return Ok(None);
+    }
+    if !todo.uuid.is_empty() && todos.iter().any(|t| t.prev_recur_todo_uuid == todo.uuid) {
+        return Ok(None);
+    }
+    let rule = Recurrence::try_from(todo.recur.as_str())?;
+    let next_due = SerdeDate::from(rule.next_due(todo.due.date(), series_anchor(todos, todo), today)).with_time(todo.due.time());
+    if todo.recur_until.is_some() && next_due > todo.recur_until {
+        return Ok(None);
+    }
+    Ok(Some(Todo {
+        id: find_new_id(todos),
+        uuid: Uuid::new_v4().to_string(),
+        due: next_due,
+        completed: false,
+        completed_date: SerdeDateTime::new_empty(),
+        status: "".to_string(),
+        archived: false,
+        prev_recur_todo_uuid: todo.uuid.clone(),
+        time_entries: vec![],
+        created: SerdeDateTime::now(),
+        ..todo.clone()
+    }))
+}
+
+pub fn complete(todos: &mut Vec<Todo>, id: u64, set: bool) -> Result<Option<Todo>, AppError> {
+    let i: usize = find_todo_index(todos, id)?;
+    let next = if set && !todos[i].completed {
+        next_occurrence(todos, &todos[i], Local::now().date_naive())?
+    } else {
+        None
+    };
+    if set && timer::running(todos) == Some(id) {
+        timer::stop(todos, Local::now())?;
+    }
+    let todo: &mut Todo = &mut todos[i];
     if set {
-        todo.status = COMPLETED_STATUS.to_string();
+        todo.status = config::current().completed_status.clone();
         todo.completed_date = SerdeDateTime::now();
     } else {
         todo.status = "".to_string();
         todo.completed_date = SerdeDateTime::new_empty();
     }
     todo.completed = set;
-    Ok(())
+    if let Some(next_todo) = &next {
+        todos.push(next_todo.clone());
+    }
+    Ok(next)
 }
 
 pub fn prioritize(todos: &mut Vec<Todo>, id: u64, set: bool) -> Result<(), AppError> {
@@ -125,23 +357,43 @@ pub fn prioritize(todos: &mut Vec<Todo>, id: u64, set: bool) -> Result<(), AppEr
     Ok(())
 }
 
-pub fn archive_completed(todos: &mut Vec<Todo>) -> () {
+/// Archives a todo together with all of its subtasks.
+pub fn archive(todos: &mut Vec<Todo>, id: u64) -> Result<(), AppError> {
+    let i: usize = find_todo_index(todos, id)?;
+    let mut archived = descendants(todos, &todos[i].uuid);
+    archived.push(todos[i].uuid.clone());
+    todos.iter_mut().filter(|t| archived.contains(&t.uuid)).for_each(|t| t.archived = true);
+    unlink_blockers(todos, &archived);
+    Ok(())
+}
+
+/// Archives completed todos, skipping any whose subtasks are not all
+/// completed so that no subtask is left under an archived parent.
+pub fn archive_completed(todos: &mut Vec<Todo>) {
+    let is_completed = |uuid: &String| todos.iter().any(|t| t.uuid == *uuid && t.completed);
+    let ready: Vec<String> = todos.iter()
+        .filter(|t| t.completed && descendants(todos, &t.uuid).iter().all(is_completed))
+        .map(|t| t.uuid.clone())
+        .collect();
     todos.iter_mut().for_each(|todo| {
-        if todo.completed {
+        if ready.contains(&todo.uuid) {
             todo.archived = true;
         }
     });
 }
 
-pub fn delete_archived(todos: &mut Vec<Todo>) -> () {
-    todos.retain(|todo| {todo.archived == false})
+pub fn delete_archived(todos: &mut Vec<Todo>) {
+    let removed: Vec<Todo> = todos.iter().filter(|todo| todo.archived).cloned().collect();
+    todos.retain(|todo| {!todo.archived});
+    unlink_blockers(todos, &removed.iter().map(|t| t.uuid.clone()).collect::<Vec<String>>());
+    reparent_orphans(todos, &removed);
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Todo, SerdeDate, SerdeDateTime};
+    use crate::{Todo, SerdeDate, SerdeDateTime, SerdeDuration};
 
     fn gen_serdedate() -> SerdeDate {
         let serdedate = SerdeDate::try_from(Some("nov28".to_string()));
@@ -167,8 +419,14 @@ mod tests {
                 is_priority: false,
                 notes: None,
                 recur: "".to_string(),
-                recur_until: "".to_string(),
-                prev_recur_todo_uuid: "".to_string()
+                recur_until: SerdeDate::new_empty(),
+                prev_recur_todo_uuid: "".to_string(),
+                parent_uuid: "".to_string(),
+                blocked_by: vec![],
+                time_entries: vec![],
+                estimate: SerdeDuration::new_empty(),
+                remind: SerdeDuration::new_empty(),
+                created: SerdeDateTime::new_empty()
             }
         ]
     }
@@ -177,9 +435,12 @@ mod tests {
     fn test_add() {
         let mut todo: Vec<Todo> = vec![];
 
-        add(&mut todo, "this is the subject".to_string(), gen_serdedate(), None);
+        let r = add(&mut todo, "this is the subject".to_string(), gen_serdedate(), None, SerdeDate::new_empty());
+        assert!(r.is_ok());
         assert!(!todo[0].uuid.is_empty());
+        assert!(todo[0].created.date().is_some());
         todo[0].uuid = "".to_string();
+        todo[0].created = SerdeDateTime::new_empty();
 
         let todo_check: Vec<Todo> = gen_todo();
         assert_eq!(todo, todo_check);
@@ -212,8 +473,8 @@ mod tests {
         let r = complete(&mut todo, 0, true);
 
         assert!(r.is_ok());
-        assert_eq!(todo[0].status, COMPLETED_STATUS);
-        assert_eq!(todo[0].completed, true);
+        assert_eq!(todo[0].status, config::current().completed_status);
+        assert!(todo[0].completed);
         assert!(todo[0].completed_date != SerdeDateTime::new_empty());
     }
 
@@ -232,14 +493,14 @@ mod tests {
         let mut todo: Vec<Todo> = gen_todo();
 
         todo[0].completed = true;
-        todo[0].status = COMPLETED_STATUS.to_string();
+        todo[0].status = config::current().completed_status.to_string();
         todo[0].completed_date = SerdeDateTime::now();
 
         let r = complete(&mut todo, 0, true);
 
         assert!(r.is_ok());
-        assert_eq!(todo[0].status, COMPLETED_STATUS);
-        assert_eq!(todo[0].completed, true);
+        assert_eq!(todo[0].status, config::current().completed_status);
+        assert!(todo[0].completed);
         assert!(todo[0].completed_date != SerdeDateTime::new_empty());
     }
 
@@ -248,14 +509,14 @@ mod tests {
         let mut todo: Vec<Todo> = gen_todo();
 
         todo[0].completed = true;
-        todo[0].status = COMPLETED_STATUS.to_string();
+        todo[0].status = config::current().completed_status.to_string();
         todo[0].completed_date = SerdeDateTime::now();
 
         let r = complete(&mut todo, 0, false);
 
         assert!(r.is_ok());
         assert_eq!(todo[0].status, "");
-        assert_eq!(todo[0].completed, false);
+        assert!(!todo[0].completed);
         assert_eq!(todo[0].completed_date, SerdeDateTime::new_empty());
     }
 
@@ -286,7 +547,7 @@ mod tests {
         let r = prioritize(&mut todo, 0, true);
 
         assert!(r.is_ok());
-        assert_eq!(todo[0].is_priority, true);
+        assert!(todo[0].is_priority);
     }
 
     #[test]
@@ -304,7 +565,7 @@ mod tests {
         let mut todo: Vec<Todo> = gen_todo();
 
         let new_subj = "this is new subject";
-        let r = edit(&mut todo, 0, new_subj.to_string(), SerdeDate::try_from(None).unwrap(), None);
+        let r = edit(&mut todo, 0, new_subj.to_string(), None, None, None);
 
         assert!(r.is_ok());
         assert_eq!(todo, vec![
@@ -322,8 +583,14 @@ mod tests {
                 is_priority: false,
                 notes: None,
                 recur: "".to_string(),
-                recur_until: "".to_string(),
-                prev_recur_todo_uuid: "".to_string()
+                recur_until: SerdeDate::new_empty(),
+                prev_recur_todo_uuid: "".to_string(),
+                parent_uuid: "".to_string(),
+                blocked_by: vec![],
+                time_entries: vec![],
+                estimate: SerdeDuration::new_empty(),
+                remind: SerdeDuration::new_empty(),
+                created: SerdeDateTime::new_empty()
             }
         ]);
     }
@@ -336,8 +603,8 @@ mod tests {
         
         archive_completed(&mut todo);
 
-        assert_eq!(todo[0].completed, true);
-        assert_eq!(todo[0].archived, true);
+        assert!(todo[0].completed);
+        assert!(todo[0].archived);
     }
 
     #[test]
@@ -355,4 +622,332 @@ mod tests {
         assert_eq!(todo.len(), 1);
         assert_eq!(todo[0].subject, "abcd");
     }
+
+    #[test]
+    fn test_add_invalid_recur() {
+        let mut todo: Vec<Todo> = vec![];
+
+        let r = add(&mut todo, "subject".to_string(), gen_serdedate(), Some("fortnightly".to_string()), SerdeDate::new_empty());
+
+        assert_eq!(r, Err(AppError::InvalidRecurrenceError("fortnightly".to_string())));
+        assert!(todo.is_empty());
+    }
+
+    #[test]
+    fn test_edit_recur() {
+        let mut todo: Vec<Todo> = gen_todo();
+
+        let r = edit(&mut todo, 0, "subject".to_string(), None, Some("every Mon,Thu".to_string()), None);
+        assert!(r.is_ok());
+        assert_eq!(todo[0].recur, "every monday,thursday");
+
+        let r = edit(&mut todo, 0, "subject".to_string(), None, Some("sometimes".to_string()), None);
+        assert_eq!(r, Err(AppError::InvalidRecurrenceError("sometimes".to_string())));
+        assert_eq!(todo[0].recur, "every monday,thursday");
+    }
+
+    #[test]
+    fn test_complete_recurring() {
+        let mut todo: Vec<Todo> = gen_todo();
+        todo[0].uuid = "first".to_string();
+        todo[0].recur = "daily".to_string();
+
+        let r = complete(&mut todo, 0, true);
+
+        assert!(r.is_ok());
+        assert_eq!(todo.len(), 2);
+        assert!(todo[0].completed);
+        assert_eq!(todo[1].id, 1);
+        assert!(!todo[1].completed);
+        assert_eq!(todo[1].completed_date, SerdeDateTime::new_empty());
+        assert_eq!(todo[1].recur, "daily");
+        assert_eq!(todo[1].prev_recur_todo_uuid, "first");
+        assert!(!todo[1].uuid.is_empty() && todo[1].uuid != "first");
+        assert!(todo[1].due > todo[0].due);
+    }
+
+    #[test]
+    fn test_complete_stops_timer() {
+        let mut todo: Vec<Todo> = gen_todo();
+        todo[0].recur = "daily".to_string();
+        assert!(timer::start(&mut todo, 0, Local::now()).is_ok());
+
+        assert!(complete(&mut todo, 0, true).is_ok());
+
+        assert_eq!(timer::running(&todo), None);
+        assert!(todo[0].time_entries()[0].end().date().is_some());
+        assert!(todo[1].time_entries().is_empty());
+    }
+
+    #[test]
+    fn test_complete_recurring_twice() {
+        let mut todo: Vec<Todo> = gen_todo();
+        todo[0].recur = "weekly".to_string();
+
+        assert!(complete(&mut todo, 0, true).is_ok());
+        assert!(complete(&mut todo, 0, true).is_ok());
+        assert!(complete(&mut todo, 0, false).is_ok());
+
+        assert_eq!(todo.len(), 2);
+    }
+
+    #[test]
+    fn test_complete_recurring_after_uncomplete() {
+        let mut todo: Vec<Todo> = gen_todo();
+        todo[0].uuid = "first".to_string();
+        todo[0].recur = "weekly".to_string();
+
+        assert!(complete(&mut todo, 0, true).is_ok());
+        assert!(complete(&mut todo, 0, false).is_ok());
+        let r = complete(&mut todo, 0, true);
+
+        assert_eq!(r, Ok(None));
+        assert_eq!(todo.len(), 2);
+    }
+
+    #[test]
+    fn test_complete_monthly_keeps_day() {
+        let mut todo: Vec<Todo> = gen_todo();
+        todo[0].uuid = "first".to_string();
+        todo[0].recur = "monthly".to_string();
+        todo[0].due = SerdeDate::from(NaiveDate::from_ymd_opt(2099, 1, 31).unwrap());
+
+        assert!(complete(&mut todo, 0, true).is_ok());
+        assert_eq!(todo[1].due, SerdeDate::from(NaiveDate::from_ymd_opt(2099, 2, 28).unwrap()));
+        assert!(complete(&mut todo, 1, true).is_ok());
+        assert_eq!(todo[2].due, SerdeDate::from(NaiveDate::from_ymd_opt(2099, 3, 31).unwrap()));
+    }
+
+    #[test]
+    fn test_edit_until() {
+        let mut todo: Vec<Todo> = gen_todo();
+
+        let r = edit(&mut todo, 0, "subject".to_string(), None, Some("daily".to_string()), Some(gen_serdedate()));
+
+        assert!(r.is_ok());
+        assert_eq!(todo[0].recur_until, gen_serdedate());
+
+        let r = edit(&mut todo, 0, String::new(), Some(SerdeDate::new_empty()), None, Some(SerdeDate::new_empty()));
+
+        assert!(r.is_ok());
+        assert_eq!(todo[0].subject, "subject");
+        assert_eq!(todo[0].due, SerdeDate::new_empty());
+        assert_eq!(todo[0].recur_until, SerdeDate::new_empty());
+    }
+
+    #[test]
+    fn test_until_without_recur() {
+        let mut todo: Vec<Todo> = gen_todo();
+
+        let r = add(&mut todo, "subject".to_string(), gen_serdedate(), Some("none".to_string()), gen_serdedate());
+        assert_eq!(r, Err(AppError::UntilWithoutRecurrenceError));
+        assert_eq!(todo.len(), 1);
+
+        let r = edit(&mut todo, 0, "changed".to_string(), None, None, Some(gen_serdedate()));
+        assert_eq!(r, Err(AppError::UntilWithoutRecurrenceError));
+        assert_eq!(todo[0].subject, "this is the subject");
+        assert_eq!(todo[0].recur_until, SerdeDate::new_empty());
+    }
+
+    #[test]
+    fn test_edit_clearing_recur_clears_until() {
+        let mut todo: Vec<Todo> = gen_todo();
+        assert!(edit(&mut todo, 0, String::new(), None, Some("daily".to_string()), Some(gen_serdedate())).is_ok());
+
+        let r = edit(&mut todo, 0, String::new(), None, Some("none".to_string()), None);
+
+        assert!(r.is_ok());
+        assert_eq!(todo[0].recur, "");
+        assert_eq!(todo[0].recur_until, SerdeDate::new_empty());
+    }
+
+    #[test]
+    fn test_complete_recurring_past_until() {
+        let mut todo: Vec<Todo> = gen_todo();
+        todo[0].recur = "weekly".to_string();
+        todo[0].recur_until = todo[0].due.clone();
+
+        let r = complete(&mut todo, 0, true);
+
+        assert!(r.is_ok());
+        assert_eq!(todo.len(), 1);
+        assert!(todo[0].completed);
+    }
+
+    #[test]
+    fn test_complete_recurring_before_until() {
+        let mut todo: Vec<Todo> = gen_todo();
+        todo[0].recur = "daily".to_string();
+        todo[0].recur_until = SerdeDate::try_from(Some("2999-01-01".to_string())).unwrap();
+
+        let r = complete(&mut todo, 0, true);
+
+        assert!(r.is_ok());
+        assert_eq!(todo.len(), 2);
+        assert_eq!(todo[1].recur_until, todo[0].recur_until);
+    }
+
+    fn gen_tree() -> Vec<Todo> {
+        // 0 > 1 > 2, and 3 on its own
+        vec![
+            Todo::builder("trip").id(0).uuid("a").build(),
+            Todo::builder("flights").id(1).uuid("b").parent_uuid("a").build(),
+            Todo::builder("compare prices").id(2).uuid("c").parent_uuid("b").build(),
+            Todo::builder("other").id(3).uuid("d").build(),
+        ]
+    }
+
+    #[test]
+    fn test_set_parent_cycle() {
+        let mut todo = gen_tree();
+
+        assert_eq!(set_parent(&mut todo, 0, 2), Err(AppError::InvalidParentError(0, 2)));
+        assert_eq!(set_parent(&mut todo, 3, 3), Err(AppError::InvalidParentError(3, 3)));
+        assert!(set_parent(&mut todo, 3, 2).is_ok());
+        assert_eq!(todo[3].parent_uuid, "c");
+        todo[0].uuid = String::new();
+        assert_eq!(set_parent(&mut todo, 3, 0), Err(AppError::InvalidParentError(3, 0)));
+    }
+
+    #[test]
+    fn test_edit_parent() {
+        let mut todo = gen_tree();
+
+        assert!(edit_parent(&mut todo, 3, "0").is_ok());
+        assert_eq!(todo[3].parent_uuid, "a");
+        assert!(edit_parent(&mut todo, 2, "none").is_ok());
+        assert_eq!(todo[2].parent_uuid, "");
+        assert_eq!(edit_parent(&mut todo, 2, "first"), Err(AppError::InvalidSelectionError("first".to_string())));
+        assert_eq!(edit_parent(&mut todo, 0, "1"), Err(AppError::InvalidParentError(0, 1)));
+    }
+
+    #[test]
+    fn test_open_subtasks() {
+        let mut todo = gen_tree();
+        todo[2].completed = true;
+
+        assert_eq!(open_subtasks(&todo, 0), Ok(vec![1]));
+        assert_eq!(open_subtasks(&todo, 3), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_delete_reparents() {
+        let mut todo = gen_tree();
+
+        assert!(delete(&mut todo, 1).is_ok());
+
+        assert_eq!(todo[1].parent_uuid, "a");
+    }
+
+    #[test]
+    fn test_reparent_cycle() {
+        let mut todo = gen_tree();
+        // a and b are each other's parent, e is its own
+        todo[0].parent_uuid = "b".to_string();
+        todo[3].parent_uuid = "e".to_string();
+        let mut removed: Vec<Todo> = todo.drain(0..2).collect();
+        removed.push(Todo::builder("own parent").id(4).uuid("e").parent_uuid("e").build());
+
+        reparent_orphans(&mut todo, &removed);
+
+        assert_eq!(todo[0].parent_uuid, "");
+        assert_eq!(todo[1].parent_uuid, "");
+    }
+
+    #[test]
+    fn test_archive_cascades() {
+        let mut todo = gen_tree();
+
+        assert!(archive(&mut todo, 1).is_ok());
+
+        let archived: Vec<bool> = todo.iter().map(|t| t.archived).collect();
+        assert_eq!(archived, vec![false, true, true, false]);
+    }
+
+    #[test]
+    fn test_archive_completed_keeps_open_subtasks() {
+        let mut todo = gen_tree();
+        todo[0].completed = true;
+        todo[2].completed = true;
+
+        archive_completed(&mut todo);
+
+        let archived: Vec<bool> = todo.iter().map(|t| t.archived).collect();
+        assert_eq!(archived, vec![false, false, true, false]);
+    }
+
+    #[test]
+    fn test_delete_archived_reparents() {
+        let mut todo = gen_tree();
+        todo[0].archived = true;
+        todo[1].archived = true;
+
+        delete_archived(&mut todo);
+
+        assert_eq!(todo.len(), 2);
+        assert_eq!(todo[0].parent_uuid, "");
+    }
+
+    #[test]
+    fn test_block_cycle() {
+        let mut todo = gen_tree();
+        assert!(block(&mut todo, 0, 1).is_ok());
+        assert!(block(&mut todo, 1, 2).is_ok());
+
+        assert_eq!(block(&mut todo, 2, 0), Err(AppError::DependencyCycleError(2, 0)));
+        assert_eq!(block(&mut todo, 3, 3), Err(AppError::DependencyCycleError(3, 3)));
+        assert_eq!(block(&mut todo, 0, 9), Err(AppError::IdNotFoundError(9)));
+        assert_eq!(todo[0].blocked_by, vec!["b"]);
+    }
+
+    #[test]
+    fn test_block_survives_id_reuse() {
+        let mut todo = gen_tree();
+        assert!(block(&mut todo, 0, 3).is_ok());
+        assert!(delete(&mut todo, 3).is_ok());
+        todo.push(Todo::builder("reuses id 3").id(3).uuid("e").build());
+
+        assert!(!is_blocked(&todo, &todo[0]));
+        assert!(todo[0].blocked_by.is_empty());
+        assert_eq!(unblock(&mut todo, 0, Some(3)), Err(AppError::NotBlockedError(0, 3)));
+    }
+
+    #[test]
+    fn test_archive_drops_blockers() {
+        let mut todo = gen_tree();
+        assert!(block(&mut todo, 0, 3).is_ok());
+        assert!(block(&mut todo, 0, 2).is_ok());
+
+        assert!(archive(&mut todo, 3).is_ok());
+
+        assert_eq!(todo[0].blocked_by, vec!["c"]);
+    }
+
+    #[test]
+    fn test_next_actions() {
+        let mut todo = gen_tree();
+        assert!(block(&mut todo, 3, 1).is_ok());
+        assert!(block(&mut todo, 0, 2).is_ok());
+        todo[2].completed = true;
+        todo[1].is_priority = true;
+
+        let next: Vec<u64> = next_actions(&todo).iter().map(|t| t.id).collect();
+
+        assert_eq!(next, vec![1, 0]);
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut todo = gen_tree();
+        assert!(set_estimate(&mut todo, 0, SerdeDuration::from_minutes(120)).is_ok());
+        assert!(set_estimate(&mut todo, 1, SerdeDuration::from_minutes(240)).is_ok());
+        assert!(set_estimate(&mut todo, 2, SerdeDuration::from_minutes(60)).is_ok());
+        todo[1].is_priority = true;
+
+        let planned: Vec<u64> = plan(&todo, SerdeDuration::from_minutes(330)).iter().map(|t| t.id).collect();
+
+        // #1 comes first as a priority, #0 no longer fits, #3 has no estimate
+        assert_eq!(planned, vec![1, 2]);
+        assert!(plan(&todo, SerdeDuration::new_empty()).is_empty());
+    }
 }
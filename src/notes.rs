@@ -3,10 +3,7 @@ use crate::{Todo, AppError, modify::{find_todo_mut}};
 pub fn add_note(todos: &mut Vec<Todo>, id: u64, note: String) -> Result<(), AppError> {
     let todo: &mut Todo = find_todo_mut(todos, id)?;
     match todo.notes.as_mut() {
-        Some(notes) => {
-            notes.push(note);
-            println!("len: {}", notes.len());
-        }
+        Some(notes) => notes.push(note),
         None => {todo.notes = Some(vec![note]);}
     }
     Ok(())
@@ -28,7 +25,7 @@ pub fn delete_note(todos: &mut Vec<Todo>, id: u64, index: usize) -> Result<(), A
     let Some(notes) = &mut todo.notes else {return Err(AppError::NoteNotFoundError(id, index))};
     if index < notes.len() {
         notes.remove(index);
-        if notes.len() == 0 {
+        if notes.is_empty() {
             todo.notes = None;
         }
         Ok(())
@@ -40,7 +37,7 @@ pub fn delete_note(todos: &mut Vec<Todo>, id: u64, index: usize) -> Result<(), A
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{Todo, SerdeDate, SerdeDateTime};
+    use crate::{Todo, SerdeDate, SerdeDateTime, SerdeDuration};
 
     fn gen_serdedate() -> SerdeDate {
         let serdedate = SerdeDate::try_from(Some("nov28".to_string()));
@@ -66,8 +63,14 @@ mod tests {
                 is_priority: false,
                 notes: None,
                 recur: "".to_string(),
-                recur_until: "".to_string(),
-                prev_recur_todo_uuid: "".to_string()
+                recur_until: SerdeDate::new_empty(),
+                prev_recur_todo_uuid: "".to_string(),
+                parent_uuid: "".to_string(),
+                blocked_by: vec![],
+                time_entries: vec![],
+                estimate: SerdeDuration::new_empty(),
+                remind: SerdeDuration::new_empty(),
+                created: SerdeDateTime::new_empty()
             }
         ]
     }
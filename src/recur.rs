@@ -0,0 +1,240 @@
+use std::fmt;
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use itertools::Itertools;
+use crate::AppError;
+use crate::agenda::MAX_DAYS;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recurrence {
+    Daily,
+    Weekdays,
+    Weekly,
+    Monthly,
+    Yearly,
+    EveryNDays(u64),
+    OnWeekdays(Vec<Weekday>),
+}
+
+fn parse_weekday(s: &str) -> Option<Weekday> {
+    match s {
+        "mon" | "monday" => Some(Weekday::Mon),
+        "tue" | "tues" | "tuesday" => Some(Weekday::Tue),
+        "wed" | "wednesday" => Some(Weekday::Wed),
+        "thu" | "thur" | "thurs" | "thursday" => Some(Weekday::Thu),
+        "fri" | "friday" => Some(Weekday::Fri),
+        "sat" | "saturday" => Some(Weekday::Sat),
+        "sun" | "sunday" => Some(Weekday::Sun),
+        _ => None
+    }
+}
+
+fn weekday_name(day: &Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "monday",
+        Weekday::Tue => "tuesday",
+        Weekday::Wed => "wednesday",
+        Weekday::Thu => "thursday",
+        Weekday::Fri => "friday",
+        Weekday::Sat => "saturday",
+        Weekday::Sun => "sunday",
+    }
+}
+
+/// Parses "every 3 days", "every day" and "every monday,thursday". Gaps
+/// longer than `MAX_DAYS`, about ten years, are refused.
+fn parse_every(rest: &str) -> Option<Recurrence> {
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    match words.as_slice() {
+        ["day"] => Some(Recurrence::Daily),
+        ["week"] => Some(Recurrence::Weekly),
+        ["month"] => Some(Recurrence::Monthly),
+        ["year"] => Some(Recurrence::Yearly),
+        ["weekday"] => Some(Recurrence::Weekdays),
+        [n, "day" | "days"] => match n.parse::<u64>() {
+            Ok(0) | Err(_) => None,
+            Ok(1) => Some(Recurrence::Daily),
+            Ok(n) if n <= MAX_DAYS => Some(Recurrence::EveryNDays(n)),
+            Ok(_) => None,
+        },
+        _ => {
+            let mut days: Vec<Weekday> = Vec::new();
+            for day in rest.split(',').map(str::trim) {
+                let weekday = parse_weekday(day)?;
+                if !days.contains(&weekday) {
+                    days.push(weekday);
+                }
+            }
+            days.sort_by_key(|d| d.num_days_from_monday());
+            Some(Recurrence::OnWeekdays(days))
+        }
+    }
+}
+
+impl TryFrom<&str> for Recurrence {
+    type Error = AppError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let lowered = value.trim().to_lowercase();
+        let parsed = match lowered.as_str() {
+            "daily" => Some(Recurrence::Daily),
+            "weekdays" => Some(Recurrence::Weekdays),
+            "weekly" => Some(Recurrence::Weekly),
+            "monthly" => Some(Recurrence::Monthly),
+            "yearly" | "annually" => Some(Recurrence::Yearly),
+            s => s.strip_prefix("every ").and_then(parse_every),
+        };
+        parsed.ok_or(AppError::InvalidRecurrenceError(value.to_string()))
+    }
+}
+
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Recurrence::Daily => write!(f, "daily"),
+            Recurrence::Weekdays => write!(f, "weekdays"),
+            Recurrence::Weekly => write!(f, "weekly"),
+            Recurrence::Monthly => write!(f, "monthly"),
+            Recurrence::Yearly => write!(f, "yearly"),
+            Recurrence::EveryNDays(n) => write!(f, "every {} days", n),
+            Recurrence::OnWeekdays(days) => write!(f, "every {}", days.iter().map(weekday_name).join(",")),
+        }
+    }
+}
+
+/// The date `months` after the month of `from`, on `day` or on the last day
+/// of that month when it is shorter.
+fn add_months_on(from: NaiveDate, months: u32, day: u32) -> NaiveDate {
+    let first = from.with_day(1).unwrap() + Months::new(months);
+    first.with_day(day).unwrap_or_else(|| first + Months::new(1) - Days::new(1))
+}
+
+impl Recurrence {
+    /// The first date strictly after `from` matching this rule.
+    pub fn next_date(&self, from: NaiveDate) -> NaiveDate {
+        self.next_date_on(from, from.day())
+    }
+
+    /// Like `next_date`, but monthly and yearly rules land on `day` of the
+    /// month, clamped to the end of shorter months.
+    fn next_date_on(&self, from: NaiveDate, day: u32) -> NaiveDate {
+        match self {
+            Recurrence::Daily => from + Days::new(1),
+            Recurrence::Weekdays => {
+                let mut next = from + Days::new(1);
+                while matches!(next.weekday(), Weekday::Sat | Weekday::Sun) {
+                    next = next + Days::new(1);
+                }
+                next
+            },
+            Recurrence::Weekly => from + Days::new(7),
+            Recurrence::Monthly => add_months_on(from, 1, day),
+            Recurrence::Yearly => add_months_on(from, 12, day),
+            Recurrence::EveryNDays(n) => from + Days::new(*n),
+            Recurrence::OnWeekdays(days) => {
+                let mut next = from + Days::new(1);
+                while !days.contains(&next.weekday()) {
+                    next = next + Days::new(1);
+                }
+                next
+            },
+        }
+    }
+
+    /// The due date of the occurrence following one due on `due`. Occurrences
+    /// that would already be in the past by `today` are skipped. `anchor` is
+    /// the due date of the first occurrence of the series: when `due` was
+    /// clamped to the end of a short month, monthly and yearly rules go back
+    /// to the anchor's day of the month instead of drifting earlier.
+    pub fn next_due(&self, due: Option<NaiveDate>, anchor: Option<NaiveDate>, today: NaiveDate) -> NaiveDate {
+        let from = due.unwrap_or(today);
+        let clamped = (from + Days::new(1)).day() == 1;
+        let day = match anchor {
+            Some(anchor) if clamped && anchor.day() > from.day() => anchor.day(),
+            _ => from.day(),
+        };
+        let mut next = self.next_date_on(from, day);
+        while next <= today {
+            next = self.next_date_on(next, day);
+        }
+        next
+    }
+}
+
+/// Validates a recurrence given on the command line, returning the string to
+/// store on the todo. "none" clears the recurrence.
+pub fn normalize_recur(recur: &str) -> Result<String, AppError> {
+    if recur.trim().is_empty() || recur.trim().eq_ignore_ascii_case("none") {
+        return Ok("".to_string());
+    }
+    Ok(Recurrence::try_from(recur)?.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Recurrence::try_from("daily"), Ok(Recurrence::Daily));
+        assert_eq!(Recurrence::try_from("Weekly"), Ok(Recurrence::Weekly));
+        assert_eq!(Recurrence::try_from("every 3 days"), Ok(Recurrence::EveryNDays(3)));
+        assert_eq!(Recurrence::try_from("every 1 day"), Ok(Recurrence::Daily));
+        assert_eq!(Recurrence::try_from("every monday,thu"), Ok(Recurrence::OnWeekdays(vec![Weekday::Mon, Weekday::Thu])));
+        assert_eq!(Recurrence::try_from("every fri, mon"), Ok(Recurrence::OnWeekdays(vec![Weekday::Mon, Weekday::Fri])));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert_eq!(Recurrence::try_from("fortnightly"), Err(AppError::InvalidRecurrenceError("fortnightly".to_string())));
+        assert!(Recurrence::try_from("every 0 days").is_err());
+        assert!(Recurrence::try_from("every monday,funday").is_err());
+        assert!(Recurrence::try_from("every").is_err());
+        assert!(Recurrence::try_from("every 99999999999 days").is_err());
+        assert_eq!(Recurrence::try_from("every 3660 days"), Ok(Recurrence::EveryNDays(3660)));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize_recur("none"), Ok("".to_string()));
+        assert_eq!(normalize_recur("every Mon,Thurs"), Ok("every monday,thursday".to_string()));
+        assert_eq!(normalize_recur("every 2 days"), Ok("every 2 days".to_string()));
+    }
+
+    #[test]
+    fn test_next_date() {
+        // 2025-01-03 is a friday
+        let fri = date(2025, 1, 3);
+        assert_eq!(Recurrence::Daily.next_date(fri), date(2025, 1, 4));
+        assert_eq!(Recurrence::Weekdays.next_date(fri), date(2025, 1, 6));
+        assert_eq!(Recurrence::Weekly.next_date(fri), date(2025, 1, 10));
+        assert_eq!(Recurrence::Monthly.next_date(date(2025, 1, 31)), date(2025, 2, 28));
+        assert_eq!(Recurrence::Yearly.next_date(fri), date(2026, 1, 3));
+        assert_eq!(Recurrence::EveryNDays(10).next_date(fri), date(2025, 1, 13));
+        assert_eq!(Recurrence::OnWeekdays(vec![Weekday::Mon, Weekday::Thu]).next_date(fri), date(2025, 1, 6));
+    }
+
+    #[test]
+    fn test_next_due_skips_past_occurrences() {
+        let today = date(2025, 1, 20);
+        assert_eq!(Recurrence::Weekly.next_due(Some(date(2025, 1, 3)), None, today), date(2025, 1, 24));
+        assert_eq!(Recurrence::Weekly.next_due(Some(date(2025, 1, 27)), None, today), date(2025, 2, 3));
+        assert_eq!(Recurrence::Daily.next_due(None, None, today), date(2025, 1, 21));
+    }
+
+    #[test]
+    fn test_next_due_keeps_anchor_day() {
+        let today = date(2025, 1, 1);
+        let anchor = Some(date(2025, 1, 31));
+        assert_eq!(Recurrence::Monthly.next_due(anchor, anchor, today), date(2025, 2, 28));
+        assert_eq!(Recurrence::Monthly.next_due(Some(date(2025, 2, 28)), anchor, today), date(2025, 3, 31));
+        assert_eq!(Recurrence::Monthly.next_due(Some(date(2025, 4, 30)), anchor, today), date(2025, 5, 31));
+        assert_eq!(Recurrence::Monthly.next_due(Some(date(2025, 2, 28)), None, today), date(2025, 3, 28));
+        // a due moved off the end of the month no longer follows the anchor
+        assert_eq!(Recurrence::Monthly.next_due(Some(date(2025, 3, 15)), anchor, today), date(2025, 4, 15));
+        let leap = Some(date(2024, 2, 29));
+        assert_eq!(Recurrence::Yearly.next_due(Some(date(2027, 2, 28)), leap, today), date(2028, 2, 29));
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::metadata;
+use std::path::PathBuf;
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+use chrono::{Local, NaiveDateTime, NaiveTime, TimeDelta};
+use notify_rust::Notification;
+use crate::Todo;
+use crate::todo_files::{get_todolist, load_todos, todolist_name, todolists};
+
+pub trait Clock {
+    fn now(&self) -> NaiveDateTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+}
+
+pub trait Notifier {
+    fn notify(&mut self, summary: &str, body: &str) -> Result<(), Box<dyn Error>>;
+}
+
+/// Sends notifications through the freedesktop D-Bus notification service.
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn notify(&mut self, summary: &str, body: &str) -> Result<(), Box<dyn Error>> {
+        Notification::new().appname("tlist").summary(summary).body(body).show()?;
+        Ok(())
+    }
+}
+
+pub struct PrintNotifier;
+
+impl Notifier for PrintNotifier {
+    fn notify(&mut self, summary: &str, body: &str) -> Result<(), Box<dyn Error>> {
+        println!("{} ({})", summary, body);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reminder {
+    Remind,
+    Due,
+    Overdue,
+}
+
+impl Reminder {
+    fn label(&self) -> &'static str {
+        match self {
+            Reminder::Remind => "Reminder",
+            Reminder::Due => "Due",
+            Reminder::Overdue => "Overdue",
+        }
+    }
+}
+
+/// When each reminder for an open todo fires. A due date without a time
+/// becomes due at the start of that day and overdue at the start of the next,
+/// one with a time is due and overdue at that time.
+pub fn reminders(todo: &Todo) -> Vec<(Reminder, NaiveDateTime)> {
+    let Some(date) = todo.due.date() else { return Vec::new() };
+    if todo.completed || todo.archived {
+        return Vec::new();
+    }
+    let due = date.and_time(todo.due.time().unwrap_or(NaiveTime::MIN));
+    let overdue = if todo.due.time().is_some() { due } else { due + TimeDelta::days(1) };
+    let mut found = Vec::new();
+    if let Some(minutes) = todo.remind.minutes().filter(|m| *m > 0) {
+        found.push((Reminder::Remind, due - TimeDelta::minutes(minutes as i64)));
+    }
+    found.push((Reminder::Due, due));
+    found.push((Reminder::Overdue, overdue));
+    found
+}
+
+/// Watches todolists and notifies each reminder once. Reminders and due
+/// dates that passed before `since` are not replayed, overdue todos are.
+pub struct Daemon<C: Clock, N: Notifier> {
+    clock: C,
+    notifier: N,
+    since: NaiveDateTime,
+    /// uuid, reminder and the due date it was sent for, so that moving a
+    /// due date arms its reminders again.
+    sent: HashSet<(String, Reminder, String)>,
+    lists: HashMap<PathBuf, (Option<SystemTime>, Vec<Todo>)>,
+}
+
+impl<C: Clock, N: Notifier> Daemon<C, N> {
+    pub fn new(clock: C, notifier: N) -> Self {
+        let since = clock.now();
+        Daemon { clock, notifier, since, sent: HashSet::new(), lists: HashMap::new() }
+    }
+
+    pub fn since(mut self, since: NaiveDateTime) -> Self {
+        self.since = since;
+        self
+    }
+
+    /// Rereads the todolists whose files changed and forgets removed ones.
+    /// A list that fails to load keeps its previous todos.
+    pub fn reload(&mut self, files: &[PathBuf]) {
+        self.lists.retain(|path, _| files.contains(path));
+        for path in files {
+            let modified = metadata(path).and_then(|m| m.modified()).ok();
+            if self.lists.get(path).is_some_and(|(m, _)| *m == modified) {
+                continue;
+            }
+            match load_todos(path) {
+                Ok(todos) => { self.lists.insert(path.clone(), (modified, todos)); },
+                Err(e) => eprintln!("Could not load {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// Sends every reminder that has come up by now, returning how many.
+    pub fn check(&mut self) -> usize {
+        let now = self.clock.now();
+        let mut due: Vec<(NaiveDateTime, Reminder, String, &Todo)> = Vec::new();
+        for (path, (_, todos)) in &self.lists {
+            let name = todolist_name(path).unwrap_or_default();
+            for todo in todos {
+                for (reminder, at) in reminders(todo) {
+                    let key = (todo.uuid.clone(), reminder, todo.due.format_iso());
+                    let missed = reminder != Reminder::Overdue && at < self.since;
+                    if at <= now && !missed && !self.sent.contains(&key) {
+                        due.push((at, reminder, name.clone(), todo));
+                    }
+                }
+            }
+        }
+        due.sort_by_key(|(at, reminder, ..)| (*at, *reminder as u8));
+
+        let mut count = 0;
+        for (_, reminder, name, todo) in due {
+            let key = |r| (todo.uuid.clone(), r, todo.due.format_iso());
+            if self.sent.contains(&key(reminder)) {
+                continue;
+            }
+            self.sent.insert(key(reminder));
+            if reminder == Reminder::Due && todo.due.time().is_some() {
+                self.sent.insert(key(Reminder::Overdue));
+            }
+            let summary = format!("{}: {}", reminder.label(), todo.subject);
+            let body = format!("{} #{}, due {}", name, todo.id, todo.due);
+            if let Err(e) = self.notifier.notify(&summary, &body) {
+                eprintln!("Could not send notification: {}", e);
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+fn todolist_files() -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    todolists()?.iter().map(|(name, _)| get_todolist(name)).collect()
+}
+
+/// Checks all todolists every `interval` and notifies on the desktop.
+pub fn run_daemon(interval: Duration) -> Result<(), Box<dyn Error>> {
+    let mut daemon = Daemon::new(SystemClock, DesktopNotifier);
+    loop {
+        daemon.reload(&todolist_files()?);
+        daemon.check();
+        sleep(interval);
+    }
+}
+
+/// Prints today's reminders once.
+pub fn remind_once() -> Result<(), Box<dyn Error>> {
+    let today = Local::now().date_naive().and_time(NaiveTime::MIN);
+    let mut daemon = Daemon::new(SystemClock, PrintNotifier).since(today);
+    daemon.reload(&todolist_files()?);
+    if daemon.check() == 0 {
+        println!("Nothing to remind of.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::env::temp_dir;
+    use std::fs::{File, create_dir_all, remove_dir_all, write};
+    use std::rc::Rc;
+    use uuid::Uuid;
+    use crate::{SerdeDate, SerdeDuration};
+
+    #[derive(Clone)]
+    struct FakeClock(Rc<Cell<NaiveDateTime>>);
+
+    impl Clock for FakeClock {
+        fn now(&self) -> NaiveDateTime {
+            self.0.get()
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct FakeNotifier(Rc<RefCell<Vec<String>>>);
+
+    impl Notifier for FakeNotifier {
+        fn notify(&mut self, summary: &str, _body: &str) -> Result<(), Box<dyn Error>> {
+            self.0.borrow_mut().push(summary.to_string());
+            Ok(())
+        }
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2025, 1, day).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn due(s: &str) -> SerdeDate {
+        serde_json::from_str(&format!("\"{}\"", s)).unwrap()
+    }
+
+    fn gen_todo() -> Vec<Todo> {
+        let mut todos = vec![
+            Todo::builder("call bob").id(0).uuid("uuid-0").due(due("2025-01-15 15:00")).remind(SerdeDuration::from_minutes(30)).build(),
+            Todo::builder("pay rent").id(1).uuid("uuid-1").due(due("2025-01-16")).build(),
+            Todo::builder("old").id(2).uuid("uuid-2").due(due("2025-01-10")).build(),
+            Todo::builder("done").id(3).uuid("uuid-3").due(due("2025-01-15")).build(),
+        ];
+        todos[3].completed = true;
+        todos
+    }
+
+    fn daemon() -> (Daemon<FakeClock, FakeNotifier>, Rc<Cell<NaiveDateTime>>, FakeNotifier) {
+        let now = Rc::new(Cell::new(at(15, 12, 0)));
+        let notifier = FakeNotifier::default();
+        (Daemon::new(FakeClock(now.clone()), notifier.clone()), now, notifier)
+    }
+
+    fn sent(notifier: &FakeNotifier) -> Vec<String> {
+        notifier.0.borrow_mut().drain(..).collect()
+    }
+
+    #[test]
+    fn test_fires_once() {
+        let (mut d, now, notifier) = daemon();
+        d.lists.insert(PathBuf::from("work.json"), (None, gen_todo()));
+
+        d.check();
+        assert_eq!(sent(&notifier), ["Overdue: old"]);
+
+        now.set(at(15, 14, 30));
+        d.check();
+        d.check();
+        assert_eq!(sent(&notifier), ["Reminder: call bob"]);
+
+        now.set(at(16, 0, 0));
+        d.check();
+        assert_eq!(sent(&notifier), ["Due: call bob", "Due: pay rent"]);
+
+        now.set(at(17, 0, 0));
+        d.check();
+        assert_eq!(sent(&notifier), ["Overdue: pay rent"]);
+    }
+
+    #[test]
+    fn test_no_replay_before_start() {
+        let (d, now, notifier) = daemon();
+        let mut d = d.since(at(16, 12, 0));
+        now.set(at(16, 12, 0));
+        d.lists.insert(PathBuf::from("work.json"), (None, gen_todo()));
+
+        d.check();
+
+        assert_eq!(sent(&notifier), ["Overdue: old", "Overdue: call bob"]);
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("work.json");
+        let (d, now, notifier) = daemon();
+        let mut d = d.since(at(16, 0, 0));
+        now.set(at(16, 0, 0));
+
+        write(&path, serde_json::to_string(&gen_todo()[1..2]).unwrap()).unwrap();
+        d.reload(std::slice::from_ref(&path));
+        d.check();
+        assert_eq!(sent(&notifier), ["Due: pay rent"]);
+
+        write(&path, serde_json::to_string(&gen_todo()[..2]).unwrap()).unwrap();
+        File::options().write(true).open(&path).unwrap().set_modified(SystemTime::now() + Duration::from_secs(10)).unwrap();
+        d.reload(std::slice::from_ref(&path));
+        d.check();
+        assert_eq!(sent(&notifier), ["Overdue: call bob"]);
+
+        d.reload(&[]);
+        assert!(d.lists.is_empty());
+        remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+use std::ops::RangeInclusive;
+use itertools::Itertools;
+use crate::{Todo, AppError};
+use crate::filter::Filter;
+
+/// Which todos a bulk command applies to: explicit ids such as `3,5,9-12`,
+/// or a filter expression such as `project:work due:overdue`. A single id
+/// is kept as a range of one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    Ids(Vec<RangeInclusive<u64>>),
+    Filter(Filter),
+}
+
+fn is_id_list(term: &str) -> bool {
+    !term.is_empty() && term.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '-')
+}
+
+fn parse_id(term: &str, id: &str) -> Result<u64, AppError> {
+    id.parse().map_err(|_| AppError::InvalidSelectionError(term.to_string()))
+}
+
+fn parse_ids(term: &str, ids: &mut Vec<RangeInclusive<u64>>) -> Result<(), AppError> {
+    for part in term.split(',').filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (parse_id(term, from)?, parse_id(term, to)?);
+                if from > to {
+                    return Err(AppError::InvalidSelectionError(term.to_string()));
+                }
+                ids.push(from..=to);
+            },
+            None => ids.push(parse_id(term, part).map(|id| id..=id)?),
+        }
+    }
+    Ok(())
+}
+
+impl Selection {
+    /// Arguments made up only of ids, commas and ranges select by id,
+    /// anything else is parsed as a filter. Blank arguments select nothing
+    /// rather than everything an empty filter would match.
+    pub fn parse(args: &[String]) -> Result<Selection, AppError> {
+        let terms: Vec<&str> = args.iter().flat_map(|a| a.split_whitespace()).collect();
+        if terms.is_empty() {
+            return Err(AppError::EmptySelectionError);
+        }
+        if !terms.iter().all(|t| is_id_list(t)) {
+            return Ok(Selection::Filter(Filter::parse(args)?));
+        }
+        let mut ids = Vec::new();
+        for term in terms {
+            parse_ids(term, &mut ids)?;
+        }
+        Ok(Selection::Ids(ids.into_iter().unique().collect()))
+    }
+
+    /// The ids of the selected todos. Ranges are sparse: `3-9` selects
+    /// whichever todos have ids from 3 to 9, however many that is. Ids
+    /// given on their own must all exist; every missing one is reported at
+    /// once, so that a bulk command can refuse to change anything.
+    pub fn resolve(&self, todos: &Vec<Todo>) -> Result<Vec<u64>, AppError> {
+        match self {
+            Selection::Ids(ranges) => {
+                let missing: Vec<u64> = ranges.iter()
+                    .filter(|r| r.start() == r.end() && !todos.iter().any(|t| t.id == *r.start()))
+                    .map(|r| *r.start())
+                    .collect();
+                let ids = ranges.iter()
+                    .flat_map(|r| todos.iter().map(|t| t.id).filter(|id| r.contains(id)).sorted())
+                    .unique()
+                    .collect();
+                if missing.is_empty() {
+                    Ok(ids)
+                } else {
+                    Err(AppError::IdsNotFoundError(missing))
+                }
+            },
+            Selection::Filter(filter) => Ok(todos.iter().filter(|t| filter.matches(t)).map(|t| t.id).collect()),
+        }
+    }
+}
+
+/// Parses and resolves a selection in one go.
+pub fn select(todos: &Vec<Todo>, args: &[String]) -> Result<Vec<u64>, AppError> {
+    Selection::parse(args)?.resolve(todos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gen_todo() -> Vec<Todo> {
+        (0..6).map(|i| Todo::builder(if i % 2 == 0 { "even +work" } else { "odd" }).id(i).build()).collect()
+    }
+
+    fn args(terms: &[&str]) -> Vec<String> {
+        terms.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_ids_and_ranges() {
+        let r = Selection::parse(&args(&["1,3", "2-4"]));
+
+        assert_eq!(r, Ok(Selection::Ids(vec![1..=1, 3..=3, 2..=4])));
+        assert_eq!(select(&gen_todo(), &args(&["5,0-1"])), Ok(vec![5, 0, 1]));
+        assert_eq!(select(&gen_todo(), &args(&["1,3", "2-4"])), Ok(vec![1, 3, 2, 4]));
+    }
+
+    #[test]
+    fn test_huge_range() {
+        let r = select(&gen_todo(), &args(&["4-18446744073709551615"]));
+
+        assert_eq!(r, Ok(vec![4, 5]));
+    }
+
+    #[test]
+    fn test_invalid_range() {
+        assert_eq!(Selection::parse(&args(&["4-2"])), Err(AppError::InvalidSelectionError("4-2".to_string())));
+        assert_eq!(Selection::parse(&args(&["3-"])), Err(AppError::InvalidSelectionError("3-".to_string())));
+    }
+
+    #[test]
+    fn test_every_missing_id() {
+        let r = select(&gen_todo(), &args(&["2,7,9,5-10"]));
+
+        assert_eq!(r, Err(AppError::IdsNotFoundError(vec![7, 9])));
+    }
+
+    #[test]
+    fn test_sparse_range() {
+        let mut todos = gen_todo();
+        todos.retain(|t| t.id != 2 && t.id != 3);
+
+        assert_eq!(select(&todos, &args(&["1-4"])), Ok(vec![1, 4]));
+        assert_eq!(select(&todos, &args(&["2-3"])), Ok(vec![]));
+        assert_eq!(select(&todos, &args(&["1-4,3"])), Err(AppError::IdsNotFoundError(vec![3])));
+    }
+
+    #[test]
+    fn test_empty_selection() {
+        assert_eq!(select(&gen_todo(), &args(&[""])), Err(AppError::EmptySelectionError));
+        assert_eq!(select(&gen_todo(), &args(&[" ", ""])), Err(AppError::EmptySelectionError));
+    }
+
+    #[test]
+    fn test_filter() {
+        let r = select(&gen_todo(), &args(&["project:work"]));
+
+        assert_eq!(r, Ok(vec![0, 2, 4]));
+    }
+}
@@ -1,11 +1,16 @@
 use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, Visitor}};
 use std::fmt;
 use std::cmp::Ordering;
-use chrono::{Days, format::{parse, Parsed, Numeric, Fixed, Item, Pad}, prelude::*, ParseError};
+use chrono::{Days, Months, prelude::*, ParseError};
+use crate::{AppError, config};
 
+/// A due date with an optional time of day. The date is stored as
+/// `YYYY-MM-DD` like ultralist does; a todo keeps the time in `due_time`
+/// next to it, see `due_fields`. `YYYY-MM-DD HH:MM` is still read.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SerdeDate {
-    date: Option<NaiveDate>
+    date: Option<NaiveDate>,
+    time: Option<NaiveTime>,
 }
 struct DateVisitor;
 
@@ -22,20 +27,25 @@ impl<'de> Visitor<'de> for DateVisitor {
     type Value = SerdeDate;
     
     fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-        formatter.write_str("A valid dateTime in the format yyyy-mm-dd")
+        formatter.write_str("A valid date in the format yyyy-mm-dd or yyyy-mm-dd hh:mm")
     }
 
     fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
     where
         E: de::Error,
     {
-        if s == "" {
-            return Ok(SerdeDate{ date: None });
+        if s.is_empty() {
+            return Ok(SerdeDate::new_empty());
         }
-        let localdate: Result<NaiveDate, ParseError> = s.parse::<NaiveDate>();
-        match localdate {
-            Ok(x) => Ok(SerdeDate { date: Some(x) }),
-            Err(_) => Err(E::custom(format!("invalid date time: {}", s)))
+        let (date, time) = match s.split_once(' ') {
+            Some((date, time)) => (date, Some(time)),
+            None => (s, None),
+        };
+        let localdate: Result<NaiveDate, ParseError> = date.parse::<NaiveDate>();
+        let localtime: Result<Option<NaiveTime>, ParseError> = time.map(|t| NaiveTime::parse_from_str(t, "%H:%M")).transpose();
+        match (localdate, localtime) {
+            (Ok(x), Ok(t)) => Ok(SerdeDate { date: Some(x), time: t }),
+            _ => Err(E::custom(format!("invalid date time: {}", s)))
         }
     }
 }
@@ -44,76 +54,302 @@ impl Serialize for SerdeDate {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where S: Serializer,
     {
-        match self.date {
-            Some(x) => serializer.serialize_str(&x.format("%Y-%m-%d").to_string()),
-            None => serializer.serialize_str("")
-        }
+        serializer.serialize_str(&self.date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_default())
+    }
+}
+
+/// A todo's `due` and `due_time` fields. ultralist only understands a plain
+/// date in `due`, so the time of day is kept in a field of its own that is
+/// left out when there is no time.
+pub(crate) mod due_fields {
+    use chrono::NaiveTime;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de};
+    use super::SerdeDate;
+
+    const TIME_FORMAT: &str = "%H:%M";
+
+    #[derive(Serialize)]
+    struct Fields<'a> {
+        due: &'a SerdeDate,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        due_time: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct StoredFields {
+        due: SerdeDate,
+        #[serde(default)]
+        due_time: Option<String>,
+    }
+
+    pub fn serialize<S: Serializer>(due: &SerdeDate, serializer: S) -> Result<S::Ok, S::Error> {
+        let due_time = due.date.and(due.time).map(|t| t.format(TIME_FORMAT).to_string());
+        Fields { due, due_time }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SerdeDate, D::Error> {
+        let stored = StoredFields::deserialize(deserializer)?;
+        let Some(time) = stored.due_time.filter(|t| !t.is_empty()) else { return Ok(stored.due) };
+        let time = NaiveTime::parse_from_str(&time, TIME_FORMAT).map_err(|_| de::Error::custom(format!("invalid due_time: {}", time)))?;
+        Ok(stored.due.with_time(Some(time)))
     }
 }
 
 impl fmt::Display for SerdeDate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.date {
-            None => write!(f, "{}", ""),
-            Some(d) => write!(f, "{}", d.format("%a %b %d").to_string()),
+        match (self.date, self.time) {
+            (None, _) => write!(f, ""),
+            (Some(d), None) => write!(f, "{}", d.format(&config::current().date_format)),
+            (Some(d), Some(t)) => write!(f, "{} {}", d.format(&config::current().date_format), t.format("%H:%M")),
+        }
+    }
+}
+
+/// The date grammar, shown in `--help` for every date argument.
+pub const DATE_HELP: &str = "\
+Dates can be written as:
+  today, tod, tomorrow, tom, yesterday
+  mon, monday          the next monday, or today if it is one
+  next monday          the first monday after today
+  nov28, nov 28, november 28, 28 november
+  28/11, 28/11/2025    day first, or month first in en_US locales
+  2025-11-28
+  +3d, +2w, +1m, +1y, -1d, in 3 days, in 2 weeks, next week, next month
+  eow, eom, eoy        the end of this week (sunday), month or year
+  tom 15:00, fri at 3pm, 9:30am
+                       a time of day may follow any date, or stand alone for today
+  none                 no date; clears the date when editing";
+
+const MONTHS: [&str; 12] = ["january", "february", "march", "april", "may", "june", "july", "august", "september", "october", "november", "december"];
+const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+/// Matches full names and any abbreviation of at least three letters.
+fn find_name(names: &[&str], word: &str) -> Option<usize> {
+    if word.len() < 3 {
+        return None;
+    }
+    names.iter().position(|n| n.starts_with(word))
+}
+
+fn month(word: &str) -> Option<u32> {
+    find_name(&MONTHS, word).map(|m| m as u32 + 1)
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    find_name(&WEEKDAYS, word).and_then(|d| Weekday::try_from(d as u8).ok())
+}
+
+fn offset(today: NaiveDate, n: i64, unit: &str) -> Option<NaiveDate> {
+    let (n, back) = (n.unsigned_abs(), n < 0);
+    let shift_days = |days: u64| if back { today.checked_sub_days(Days::new(days)) } else { today.checked_add_days(Days::new(days)) };
+    let shift_months = |months: u64| {
+        let months = Months::new(months.try_into().ok()?);
+        if back { today.checked_sub_months(months) } else { today.checked_add_months(months) }
+    };
+    match unit {
+        "d" | "day" | "days" => shift_days(n),
+        "w" | "week" | "weeks" => shift_days(n.checked_mul(7)?),
+        "m" | "month" | "months" => shift_months(n),
+        "y" | "year" | "years" => shift_months(n.checked_mul(12)?),
+        _ => None,
+    }
+}
+
+/// A day and month without a year: this year, unless it has already passed.
+fn month_day(today: NaiveDate, month: u32, day: u32) -> Option<NaiveDate> {
+    let date = NaiveDate::from_ymd_opt(today.year(), month, day)?;
+    if date < today { NaiveDate::from_ymd_opt(today.year() + 1, month, day) } else { Some(date) }
+}
+
+fn numeric(today: NaiveDate, word: &str, month_first: bool) -> Option<NaiveDate> {
+    let parts: Vec<u32> = word.split('/').map(|p| p.parse().ok()).collect::<Option<Vec<u32>>>()?;
+    let (day, month) = match parts[..] {
+        [a, b] | [a, b, _] if month_first => (b, a),
+        [a, b] | [a, b, _] => (a, b),
+        _ => return None,
+    };
+    match parts[..] {
+        [_, _, year] => NaiveDate::from_ymd_opt(year.try_into().ok()?, month, day),
+        _ => month_day(today, month, day),
+    }
+}
+
+/// "15:00", "9:30", "3pm" or "3:30pm".
+fn time_of_day(word: &str) -> Option<NaiveTime> {
+    let (clock, pm) = match (word.strip_suffix("am"), word.strip_suffix("pm")) {
+        (Some(clock), _) => (clock, Some(false)),
+        (_, Some(clock)) => (clock, Some(true)),
+        _ => (word, None),
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour, minute),
+        None if pm.is_some() => (clock, "00"),
+        _ => return None,
+    };
+    let (hour, minute): (u32, u32) = (hour.parse().ok()?, minute.parse().ok()?);
+    let hour = match pm {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+        None => hour,
+    };
+    NaiveTime::from_hms_opt(hour, minute, 0)
+}
+
+/// Splits "nov28" into its letters and digits.
+fn split_letters(word: &str) -> (&str, &str) {
+    word.split_at(word.find(|c: char| c.is_ascii_digit()).unwrap_or(word.len()))
+}
+
+/// Whether `value` is a weekday on its own, like "tues" or "Friday",
+/// which `parse_at` places on or after today.
+pub(crate) fn is_weekday(value: &str) -> bool {
+    weekday(&value.trim().to_lowercase()).is_some()
+}
+
+/// Whether `value` is a day and month without a year, like "nov28",
+/// "28 nov" or "11/28", which `parse_at` places on or after today.
+pub(crate) fn is_month_day(value: &str) -> bool {
+    let lower = value.trim().to_lowercase();
+    match lower.split_whitespace().collect::<Vec<&str>>()[..] {
+        [word] if word.contains('/') => word.split('/').count() == 2,
+        [word] => weekday(word).is_none() && month(split_letters(word).0).is_some(),
+        [a, b] => month(a).is_some() || month(b).is_some(),
+        _ => false,
+    }
+}
+
+/// Whether numeric dates put the month first, judging by the locale.
+pub(crate) fn month_first_locale() -> bool {
+    ["LC_ALL", "LC_TIME", "LANG"].iter()
+        .filter_map(|v| std::env::var(v).ok())
+        .find(|v| !v.is_empty())
+        .is_some_and(|v| v.starts_with("en_US"))
+}
+
+impl SerdeDate {
+    /// Parses a date in the `DATE_HELP` grammar relative to `today`.
+    pub fn parse_at(value: &str, today: NaiveDate, month_first: bool) -> Result<SerdeDate, AppError> {
+        let lower = value.trim().to_lowercase();
+        let mut words: Vec<&str> = lower.split_whitespace().collect();
+        let time = words.last().and_then(|w| time_of_day(w));
+        if time.is_some() {
+            words.pop();
+            if words.last() == Some(&"at") {
+                words.pop();
+            }
         }
+        let date = match words[..] {
+            ["none"] if time.is_none() => return Ok(SerdeDate::new_empty()),
+            [] if time.is_some() => Some(today),
+            ["today" | "tod"] => Some(today),
+            ["tomorrow" | "tom"] => today.succ_opt(),
+            ["yesterday"] => today.pred_opt(),
+            ["eow"] => Some(today + Days::new(6 - u64::from(today.weekday().num_days_from_monday()))),
+            ["eom"] => offset(today.with_day(1).unwrap_or(today), 1, "m").and_then(|d| d.pred_opt()),
+            ["eoy"] => NaiveDate::from_ymd_opt(today.year(), 12, 31),
+            ["in", n, unit] => n.parse().ok().and_then(|n| offset(today, n, unit)),
+            ["next", unit @ ("week" | "month" | "year")] => offset(today, 1, unit),
+            ["next", day] => weekday(day).map(|d| today + Days::new(match d.days_since(today.weekday()) { 0 => 7, n => n.into() })),
+            [word] if word.starts_with(['+', '-']) => {
+                let end = word[1..].find(|c: char| !c.is_ascii_digit()).map_or(word.len(), |i| i + 1);
+                word[..end].parse().ok().and_then(|n| offset(today, n, &word[end..]))
+            },
+            [word] if word.contains('/') => numeric(today, word, month_first),
+            [word] if word.contains('-') => NaiveDate::parse_from_str(word, "%Y-%m-%d").ok(),
+            [word] => match (weekday(word), split_letters(word)) {
+                (Some(d), _) => Some(today + Days::new(d.days_since(today.weekday()).into())),
+                (None, (m, d)) => month(m).zip(d.parse().ok()).and_then(|(m, d)| month_day(today, m, d)),
+            },
+            [a, b] => match (month(a), month(b)) {
+                (Some(m), _) => b.parse().ok().and_then(|d| month_day(today, m, d)),
+                (_, Some(m)) => a.parse().ok().and_then(|d| month_day(today, m, d)),
+                _ => None,
+            },
+            _ => None,
+        };
+        date.map(|d| SerdeDate::from(d).with_time(time)).ok_or(AppError::InvalidDateError(value.to_string()))
     }
 }
 
-const PARSE_OPTIONS: [&[Item]; 3] = [
-    &[Item::Fixed(Fixed::ShortMonthName), Item::Numeric(Numeric::Day, Pad::Zero)],
-    &[Item::Fixed(Fixed::ShortWeekdayName)],
-    &[Item::Numeric(Numeric::Year, Pad::Zero), Item::Literal("-"), Item::Numeric(Numeric::Month, Pad::Zero), Item::Literal("-"), Item::Numeric(Numeric::Day, Pad::Zero)]
-];
 impl TryFrom<Option<String>> for SerdeDate {
-    type Error = ParseError;
+    type Error = AppError;
     fn try_from(value: Option<String>) -> Result<Self, Self::Error> {
         match value {
-            None => Ok(SerdeDate { date: None }),
-            Some(d) => {
-                let today = Local::now().date_naive();
-                let parsed_date = match d.as_str() {
-                    "today" => today,
-                    "tod" => today,
-                    "tomorrow" => today + Days::new(1),
-                    "tom" => today + Days::new(1),
-                    _ => {
-                        let mut parsed = Parsed::new();
-                        PARSE_OPTIONS.iter().fold(
-                            parse(&mut parsed, &d, PARSE_OPTIONS[0].iter()), 
-                            |res, opt| res.or(parse(&mut parsed, &d, opt.iter()))
-                        )?;
-                        if let Some(weekday) = parsed.weekday() {
-                            let diff = weekday.days_since(today.weekday());
-                            return Ok(SerdeDate { date: Some(today + Days::new(diff.into()))});
-                        }
-                        let _ = parsed.set_year((today.year() + 
-                            if today.month() * 31 + today.day() < 
-                            parsed.month().unwrap() * 31 + parsed.day().unwrap() 
-                                { 0 } else { 1 }).into());
-                        parsed.to_naive_date()?
-                    }
-                };
-                Ok(SerdeDate { date: Some(parsed_date) })
-            },
+            None => Ok(SerdeDate::new_empty()),
+            Some(d) => SerdeDate::parse_at(&d, Local::now().date_naive(), month_first_locale()),
         }
     }
 }
 
 impl SerdeDate {
+    pub fn new_empty() -> Self {
+        SerdeDate {
+            date: None,
+            time: None,
+        }
+    }
+
     pub fn is_some(&self) -> bool {
         self.date.is_some()
     }
 
     pub fn today() -> Self {
-        SerdeDate {
-            date: Some(Local::now().date_naive())
+        SerdeDate::from(Local::now().date_naive())
+    }
+
+    pub fn date(&self) -> Option<NaiveDate> {
+        self.date
+    }
+
+    pub fn time(&self) -> Option<NaiveTime> {
+        self.time
+    }
+
+    pub fn with_time(self, time: Option<NaiveTime>) -> Self {
+        SerdeDate { time, ..self }
+    }
+
+    /// The on-disk form, `YYYY-MM-DD` or `YYYY-MM-DD HH:MM`.
+    pub fn format_iso(&self) -> String {
+        match (self.date, self.time) {
+            (None, _) => String::new(),
+            (Some(d), None) => d.format("%Y-%m-%d").to_string(),
+            (Some(d), Some(t)) => format!("{} {}", d.format("%Y-%m-%d"), t.format("%H:%M")),
         }
     }
 
-    pub fn cmp(&self, other: &SerdeDate) -> Ordering {
+    /// A date without a time is overdue from the next day on, one with a
+    /// time as soon as that time has passed.
+    pub fn is_overdue_at(&self, now: NaiveDateTime) -> bool {
+        match (self.date, self.time) {
+            (None, _) => false,
+            (Some(d), None) => d < now.date(),
+            (Some(d), Some(t)) => d.and_time(t) < now,
+        }
+    }
+
+    pub fn is_overdue(&self) -> bool {
+        self.is_overdue_at(Local::now().naive_local())
+    }
+}
+
+impl From<NaiveDate> for SerdeDate {
+    fn from(date: NaiveDate) -> Self {
+        SerdeDate { date: Some(date), time: None }
+    }
+}
+
+impl PartialOrd for SerdeDate {
+    fn partial_cmp(&self, other: &SerdeDate) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SerdeDate {
+    fn cmp(&self, other: &SerdeDate) -> Ordering {
         if let (Some(this_date_i), Some(other_date_i)) = (self.date, other.date) {
-            return this_date_i.cmp(&other_date_i);
+            // on the same day, a todo with a time comes before one without
+            (this_date_i, self.time.is_none(), self.time).cmp(&(other_date_i, other.time.is_none(), other.time))
         } else {
             if self.date.is_none() && other.date.is_none() { 
                 return Ordering::Equal;
@@ -122,3 +358,120 @@ impl SerdeDate {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(y, m, d)
+    }
+
+    #[test]
+    fn test_parse_at() {
+        // a wednesday
+        let today = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        let cases = [
+            ("today", date(2025, 1, 15)),
+            ("tom", date(2025, 1, 16)),
+            ("yesterday", date(2025, 1, 14)),
+            ("wed", date(2025, 1, 15)),
+            ("friday", date(2025, 1, 17)),
+            ("Monday", date(2025, 1, 20)),
+            ("next wednesday", date(2025, 1, 22)),
+            ("next fri", date(2025, 1, 17)),
+            ("+3d", date(2025, 1, 18)),
+            ("-1d", date(2025, 1, 14)),
+            ("+2w", date(2025, 1, 29)),
+            ("+1m", date(2025, 2, 15)),
+            ("+1y", date(2026, 1, 15)),
+            ("in 2 weeks", date(2025, 1, 29)),
+            ("in 1 day", date(2025, 1, 16)),
+            ("next month", date(2025, 2, 15)),
+            ("eow", date(2025, 1, 19)),
+            ("eom", date(2025, 1, 31)),
+            ("eoy", date(2025, 12, 31)),
+            ("nov28", date(2025, 11, 28)),
+            ("jan15", date(2025, 1, 15)),
+            ("jan14", date(2026, 1, 14)),
+            ("november 28", date(2025, 11, 28)),
+            ("28 November", date(2025, 11, 28)),
+            ("28/11", date(2025, 11, 28)),
+            ("28/11/2027", date(2027, 11, 28)),
+            ("2025-11-28", date(2025, 11, 28)),
+            ("none", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(SerdeDate::parse_at(input, today, false).map(|d| d.date()), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_at_month_first() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        let cases = [
+            ("11/28", date(2025, 11, 28)),
+            ("2/3/2026", date(2026, 2, 3)),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(SerdeDate::parse_at(input, today, true).map(|d| d.date()), Ok(expected), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_at_invalid() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        for input in ["", "soon", "feb30", "28/13", "+3x", "in two weeks", "next", "ju", "2025-13-01", "+9223372036854775807w", "-9223372036854775808y"] {
+            assert_eq!(SerdeDate::parse_at(input, today, false), Err(AppError::InvalidDateError(input.to_string())), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_at_time() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0);
+        let cases = [
+            ("tom 15:00", date(2025, 1, 16), time(15, 0)),
+            ("fri at 3pm", date(2025, 1, 17), time(15, 0)),
+            ("nov 28 9:30am", date(2025, 11, 28), time(9, 30)),
+            ("2025-11-28 12:15", date(2025, 11, 28), time(12, 15)),
+            ("12am", date(2025, 1, 15), time(0, 0)),
+            ("nov 15", date(2025, 11, 15), None),
+        ];
+        for (input, expected, expected_time) in cases {
+            let r = SerdeDate::parse_at(input, today, false).unwrap();
+            assert_eq!((r.date(), r.time()), (expected, expected_time), "{}", input);
+        }
+        for input in ["tom 25:00", "tom 13pm", "tom 3:5", "none 15:00"] {
+            assert_eq!(SerdeDate::parse_at(input, today, false), Err(AppError::InvalidDateError(input.to_string())), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_serialize() {
+        let dates: Vec<SerdeDate> = serde_json::from_str(r#"["2025-11-28", "2025-11-28 15:00", ""]"#).unwrap();
+
+        assert_eq!(dates[0].time(), None);
+        assert_eq!(dates[1].time(), NaiveTime::from_hms_opt(15, 0, 0));
+        assert!(!dates[2].is_some());
+        assert_eq!(serde_json::to_string(&dates).unwrap(), r#"["2025-11-28","2025-11-28",""]"#);
+        assert!(serde_json::from_str::<SerdeDate>(r#""2025-11-28 3pm""#).is_err());
+    }
+
+    #[test]
+    fn test_overdue_and_order() {
+        let day = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+        let now = day.and_hms_opt(15, 30, 0).unwrap();
+        let at = |h| SerdeDate::from(day).with_time(NaiveTime::from_hms_opt(h, 0, 0));
+
+        assert!(at(15).is_overdue_at(now));
+        assert!(!at(16).is_overdue_at(now));
+        assert!(!SerdeDate::from(day).is_overdue_at(now));
+        assert!(SerdeDate::from(day).is_overdue_at(now + chrono::TimeDelta::days(1)));
+        assert!(!SerdeDate::new_empty().is_overdue_at(now));
+
+        let mut dates = vec![SerdeDate::new_empty(), SerdeDate::from(day), at(16), at(9)];
+        dates.sort();
+        assert_eq!(dates, vec![at(9), at(16), SerdeDate::from(day), SerdeDate::new_empty()]);
+    }
+}
@@ -2,7 +2,7 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, Visitor
 use std::fmt;
 use chrono::{prelude::*, ParseError};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct SerdeDateTime {
     date: Option<DateTime<Local>>
 }
@@ -28,7 +28,7 @@ impl<'de> Visitor<'de> for DateTimeVisitor {
     where
         E: de::Error,
     {
-        if s == "" {
+        if s.is_empty() {
             return Ok(SerdeDateTime { date: None });
         }
         let localdate: Result<DateTime<Local>, ParseError> = s.parse::<DateTime<Local>>();
@@ -50,6 +50,15 @@ impl Serialize for SerdeDateTime {
     }
 }
 
+impl fmt::Display for SerdeDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.date {
+            None => write!(f, ""),
+            Some(d) => write!(f, "{}", d.format("%Y-%m-%d %H:%M")),
+        }
+    }
+}
+
 impl SerdeDateTime {
     pub fn new_empty() -> Self {
         SerdeDateTime {
@@ -62,4 +71,14 @@ impl SerdeDateTime {
             date: Some(Local::now())
         }
     }
-}
\ No newline at end of file
+
+    pub fn date(&self) -> Option<DateTime<Local>> {
+        self.date
+    }
+}
+
+impl From<DateTime<Local>> for SerdeDateTime {
+    fn from(date: DateTime<Local>) -> Self {
+        SerdeDateTime { date: Some(date) }
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::{self, Visitor}};
+use std::fmt;
+use std::iter::Sum;
+use crate::AppError;
+
+/// A whole number of minutes such as an estimate, written like "1h30m".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct SerdeDuration {
+    minutes: Option<u64>
+}
+struct DurationVisitor;
+
+impl<'de> Deserialize<'de> for SerdeDuration {
+    fn deserialize<D>(deserializer: D) -> Result<SerdeDuration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_string(DurationVisitor)
+    }
+}
+
+impl<'de> Visitor<'de> for DurationVisitor {
+    type Value = SerdeDuration;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("A duration in the format 1h30m")
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        SerdeDuration::try_from(s).map_err(|_| E::custom(format!("invalid duration: {}", s)))
+    }
+}
+
+impl Serialize for SerdeDuration {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl fmt::Display for SerdeDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.minutes {
+            None => write!(f, ""),
+            Some(m) if m % 60 == 0 && m > 0 => write!(f, "{}h", m / 60),
+            Some(m) if m > 60 => write!(f, "{}h{}m", m / 60, m % 60),
+            Some(m) => write!(f, "{}m", m),
+        }
+    }
+}
+
+/// Parses "2h", "30m", "1h30m" or "1.5h". An empty string or "none" gives
+/// no duration.
+impl TryFrom<&str> for SerdeDuration {
+    type Error = AppError;
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || AppError::InvalidDurationError(value.to_string());
+        if value.is_empty() || value == "none" {
+            return Ok(SerdeDuration::new_empty());
+        }
+        let mut minutes = 0.0;
+        let mut number = String::new();
+        for c in value.chars() {
+            match c {
+                '0'..='9' | '.' => number.push(c),
+                'h' | 'm' => {
+                    let n: f64 = number.parse().map_err(|_| invalid())?;
+                    minutes += if c == 'h' { n * 60.0 } else { n };
+                    number.clear();
+                },
+                _ => return Err(invalid()),
+            }
+        }
+        if !number.is_empty() {
+            return Err(invalid());
+        }
+        Ok(SerdeDuration::from_minutes(minutes.round() as u64))
+    }
+}
+
+impl Sum for SerdeDuration {
+    fn sum<I: Iterator<Item = SerdeDuration>>(iter: I) -> Self {
+        iter.fold(SerdeDuration::new_empty(), |total, d| match (total.minutes, d.minutes) {
+            (Some(a), Some(b)) => SerdeDuration::from_minutes(a + b),
+            (a, b) => SerdeDuration { minutes: a.or(b) },
+        })
+    }
+}
+
+impl SerdeDuration {
+    pub fn new_empty() -> Self {
+        SerdeDuration {
+            minutes: None
+        }
+    }
+
+    pub fn from_minutes(minutes: u64) -> Self {
+        SerdeDuration {
+            minutes: Some(minutes)
+        }
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.minutes.is_some()
+    }
+
+    pub fn minutes(&self) -> Option<u64> {
+        self.minutes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(SerdeDuration::try_from("2h"), Ok(SerdeDuration::from_minutes(120)));
+        assert_eq!(SerdeDuration::try_from("30m"), Ok(SerdeDuration::from_minutes(30)));
+        assert_eq!(SerdeDuration::try_from("1h30m"), Ok(SerdeDuration::from_minutes(90)));
+        assert_eq!(SerdeDuration::try_from("1.5h"), Ok(SerdeDuration::from_minutes(90)));
+        assert_eq!(SerdeDuration::try_from("none"), Ok(SerdeDuration::new_empty()));
+        assert_eq!(SerdeDuration::try_from("90"), Err(AppError::InvalidDurationError("90".to_string())));
+        assert_eq!(SerdeDuration::try_from("2 hours"), Err(AppError::InvalidDurationError("2 hours".to_string())));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(SerdeDuration::from_minutes(90).to_string(), "1h30m");
+        assert_eq!(SerdeDuration::from_minutes(120).to_string(), "2h");
+        assert_eq!(SerdeDuration::from_minutes(45).to_string(), "45m");
+        assert_eq!(SerdeDuration::new_empty().to_string(), "");
+    }
+}
@@ -0,0 +1,403 @@
+use std::error::Error;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tiny_http::{Header, Method, Response, Server};
+use crate::{AppError, SerdeDate, Todo};
+use crate::filter::Filter;
+use crate::modify::{add, archive, complete, describe_todo, edit, find_todo_index};
+use crate::notes::{add_note, delete_note, edit_note};
+use crate::todo_files::{Transaction, get_active_todo, load_todos, todolists_in};
+
+/// The body of a create or edit request. Dates use the same grammar as
+/// the command line.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TodoBody {
+    subject: Option<String>,
+    due: Option<String>,
+    recur: Option<String>,
+    until: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoteBody {
+    note: String,
+}
+
+/// An HTTP status and its JSON body.
+type Reply = (u16, Value);
+
+fn error(status: u16, message: impl Display) -> Reply {
+    (status, json!({ "error": message.to_string() }))
+}
+
+fn error_reply(e: Box<dyn Error>) -> Reply {
+    match e.downcast_ref::<AppError>() {
+        Some(AppError::IdNotFoundError(_) | AppError::NoteNotFoundError(..) | AppError::TodolistNotFoundError(_)) => error(404, e),
+        Some(AppError::TodolistChangedError(_)) => error(409, e),
+        Some(_) => error(400, e),
+        None if e.is::<serde_json::Error>() => error(400, e),
+        None => error(500, e),
+    }
+}
+
+fn parse_date(date: Option<String>) -> Result<Option<SerdeDate>, AppError> {
+    date.map(|d| SerdeDate::try_from(Some(d))).transpose()
+}
+
+fn parse_id(segment: &str) -> Result<u64, Box<dyn Error>> {
+    Ok(segment.parse().map_err(|_| AppError::InvalidSelectionError(segment.to_string()))?)
+}
+
+/// Decodes `%XX` escapes and `+` in a query string value.
+fn decode(value: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = value.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (b, hex) {
+            (b'%', Some(h)) => { bytes.push(h); rest = &tail[2..]; },
+            (b'+', _) => { bytes.push(b' '); rest = tail; },
+            _ => { bytes.push(b); rest = tail; },
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+fn query_param(query: &str, key: &str) -> Option<String> {
+    query.split('&').filter_map(|p| p.split_once('=')).find(|(k, _)| *k == key).map(|(_, v)| decode(v))
+}
+
+fn header<'a>(headers: &'a [Header], name: &'static str) -> Option<&'a str> {
+    headers.iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str())
+}
+
+/// Whether a Host header value, or an origin without its scheme, names
+/// this machine.
+fn is_loopback(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    matches!(name.to_lowercase().as_str(), "localhost" | "127.0.0.1" | "::1")
+}
+
+/// The request's Origin if a page on this machine sent it. Requests
+/// without one come from other programs rather than browsers.
+fn allowed_origin(headers: &[Header]) -> Result<Option<&str>, Reply> {
+    let Some(origin) = header(headers, "Origin") else { return Ok(None) };
+    match origin.strip_prefix("http://").or_else(|| origin.strip_prefix("https://")) {
+        Some(host) if is_loopback(host) => Ok(Some(origin)),
+        _ => Err(error(403, format!("origin {} is not allowed", origin))),
+    }
+}
+
+/// Refuses requests that did not come from this machine, so a web page
+/// elsewhere cannot reach the API through the browser, and changes whose
+/// body is not JSON, which browsers would otherwise send without asking.
+fn check_request(method: &Method, headers: &[Header]) -> Result<Option<String>, Reply> {
+    match header(headers, "Host") {
+        Some(host) if is_loopback(host) => (),
+        host => return Err(error(403, format!("host {} is not allowed", host.unwrap_or("(none)")))),
+    }
+    let origin = allowed_origin(headers)?.map(|o| o.to_string());
+    let is_json = header(headers, "Content-Type")
+        .is_some_and(|t| t.split(';').next().unwrap_or_default().trim().eq_ignore_ascii_case("application/json"));
+    if matches!(method, Method::Post | Method::Put | Method::Patch) && !is_json {
+        return Err(error(415, "the body must be sent as Content-Type: application/json"));
+    }
+    Ok(origin)
+}
+
+/// The CORS headers for a page at `origin`, with those a preflight needs.
+fn cors_headers(origin: &str, preflight: bool) -> Vec<Header> {
+    let mut headers = vec![("Access-Control-Allow-Origin", origin), ("Vary", "Origin")];
+    if preflight {
+        headers.extend([
+            ("Access-Control-Allow-Methods", "GET, POST, PUT, PATCH, DELETE"),
+            ("Access-Control-Allow-Headers", "Content-Type"),
+            ("Access-Control-Max-Age", "600"),
+        ]);
+    }
+    headers.into_iter().map(|(k, v)| Header::from_bytes(k, v).unwrap()).collect()
+}
+
+/// The todolists in a config dir, served over HTTP. Every change goes
+/// through a `Transaction`, so it is locked, journaled and committed just
+/// like the same change made with the CLI.
+pub struct Api {
+    confdir: PathBuf,
+}
+
+impl Api {
+    pub fn new(confdir: &Path) -> Api {
+        Api { confdir: confdir.to_path_buf() }
+    }
+
+    fn todolist(&self, name: &str) -> Result<PathBuf, Box<dyn Error>> {
+        let exists = todolists_in(&self.confdir)?.iter().any(|(n, _)| n == name);
+        if !exists {
+            return Err(Box::new(AppError::TodolistNotFoundError(name.to_string())));
+        }
+        Ok(self.confdir.join("todolists").join(format!("{}.json", name)))
+    }
+
+    /// Answers one request. `url` is the path with its query string.
+    pub fn handle(&self, method: &Method, url: &str, body: &str) -> Reply {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+        let result = match segments[..] {
+            ["todolists"] if *method == Method::Get => self.list_todolists(),
+            ["todos", ref rest @ ..] => self.route(method, &self.confdir.join("active_todos.json"), rest, query, body),
+            ["todolists", name, "todos", ref rest @ ..] => match self.todolist(name) {
+                Ok(list) => self.route(method, &list, rest, query, body),
+                Err(e) => Err(e),
+            },
+            _ => return error(404, format!("no route for {} {}", method, path)),
+        };
+        result.unwrap_or_else(error_reply)
+    }
+
+    fn list_todolists(&self) -> Result<Reply, Box<dyn Error>> {
+        let lists: Vec<Value> = todolists_in(&self.confdir)?.into_iter()
+            .map(|(name, active)| json!({ "name": name, "active": active }))
+            .collect();
+        Ok((200, json!(lists)))
+    }
+
+    fn route(&self, method: &Method, list: &Path, rest: &[&str], query: &str, body: &str) -> Result<Reply, Box<dyn Error>> {
+        match (method, rest) {
+            (Method::Get, []) => {
+                let terms: Vec<String> = query_param(query, "filter").into_iter().collect();
+                let filter = Filter::parse(&terms)?;
+                let todos: Vec<Todo> = load_todos(list)?.into_iter().filter(|t| filter.matches(t)).collect();
+                Ok((200, serde_json::to_value(todos)?))
+            },
+            (Method::Get, [id]) => {
+                let id = parse_id(id)?;
+                let todos = load_todos(list)?;
+                Ok((200, serde_json::to_value(&todos[find_todo_index(&todos, id)?])?))
+            },
+            (Method::Post, []) => {
+                let b: TodoBody = serde_json::from_str(body)?;
+                let subject = b.subject.unwrap_or_default();
+                let (due, until) = (parse_date(b.due)?.unwrap_or(SerdeDate::new_empty()), parse_date(b.until)?.unwrap_or(SerdeDate::new_empty()));
+                let mut tx = Transaction::open(list)?;
+                let id = add(&mut tx.todos, subject.clone(), due, b.recur, until)?;
+                let todo = serde_json::to_value(&tx.todos[find_todo_index(&tx.todos, id)?])?;
+                tx.commit(Some(&format!("add: {}", subject)))?;
+                Ok((201, todo))
+            },
+            (Method::Patch, [id]) => {
+                let id = parse_id(id)?;
+                let b: TodoBody = serde_json::from_str(body)?;
+                let (due, until) = (parse_date(b.due)?, parse_date(b.until)?);
+                self.mutate(list, "edit", id, move |t| edit(t, id, b.subject.unwrap_or_default(), due, b.recur, until))
+            },
+            (Method::Post, [id, action @ ("complete" | "uncomplete" | "archive")]) => {
+                let id = parse_id(id)?;
+                match *action {
+                    "archive" => self.mutate(list, action, id, |t| archive(t, id)),
+                    _ => self.mutate(list, action, id, |t| complete(t, id, *action == "complete").map(|_| ())),
+                }
+            },
+            (Method::Post, [id, "notes"]) => {
+                let id = parse_id(id)?;
+                let b: NoteBody = serde_json::from_str(body)?;
+                self.mutate(list, "add note to", id, |t| add_note(t, id, b.note))
+            },
+            (Method::Put, [id, "notes", index]) => {
+                let (id, index) = (parse_id(id)?, parse_id(index)? as usize);
+                let b: NoteBody = serde_json::from_str(body)?;
+                self.mutate(list, &format!("edit note {} on", index), id, |t| edit_note(t, id, index, b.note))
+            },
+            (Method::Delete, [id, "notes", index]) => {
+                let (id, index) = (parse_id(id)?, parse_id(index)? as usize);
+                self.mutate(list, &format!("delete note {} on", index), id, |t| delete_note(t, id, index))
+            },
+            _ => Ok(error(404, format!("no route for {} {}", method, rest.join("/")))),
+        }
+    }
+
+    /// Applies `f` to the todolist, journaled as "<verb> #id: subject", and
+    /// replies with the changed todo.
+    fn mutate(&self, list: &Path, verb: &str, id: u64, f: impl FnOnce(&mut Vec<Todo>) -> Result<(), AppError>) -> Result<Reply, Box<dyn Error>> {
+        let mut tx = Transaction::open(list)?;
+        let operation = format!("{} {}", verb, describe_todo(&tx.todos, id));
+        f(&mut tx.todos)?;
+        let todo = serde_json::to_value(&tx.todos[find_todo_index(&tx.todos, id)?])?;
+        tx.commit(Some(&operation))?;
+        Ok((200, todo))
+    }
+}
+
+/// Serves the API on localhost until the process is stopped.
+pub fn serve(port: u16) -> Result<(), Box<dyn Error>> {
+    let confdir = get_active_todo()?.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+    let api = Api::new(&confdir);
+    let server = Server::http(("127.0.0.1", port)).map_err(|e| e.to_string())?;
+    let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+    println!("Serving {} on http://127.0.0.1:{}", confdir.display(), port);
+    for mut request in server.incoming_requests() {
+        let preflight = *request.method() == Method::Options;
+        let checked = match preflight {
+            true => allowed_origin(request.headers()).map(|o| o.map(|o| o.to_string())),
+            false => check_request(request.method(), request.headers()),
+        };
+        let mut body = String::new();
+        let (origin, (status, reply)) = match checked {
+            Err(reply) => (None, reply),
+            Ok(origin) if preflight => (origin, (204, Value::Null)),
+            Ok(origin) => match request.as_reader().read_to_string(&mut body) {
+                Ok(_) => (origin, api.handle(request.method(), request.url(), &body)),
+                Err(e) => (origin, error(400, e)),
+            },
+        };
+        let text = if reply.is_null() { String::new() } else { reply.to_string() };
+        let mut response = Response::from_string(text).with_status_code(status).with_header(content_type.clone());
+        for cors in origin.map(|o| cors_headers(&o, preflight)).unwrap_or_default() {
+            response.add_header(cors);
+        }
+        if let Err(e) = request.respond(response) {
+            eprintln!("Could not respond: {}", e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::os::unix::fs::symlink;
+    use uuid::Uuid;
+    use crate::todo_files::save_todos;
+
+    fn gen_todo() -> Vec<Todo> {
+        vec![
+            Todo::builder("Buy milk +groceries").id(0).build(),
+            Todo::builder("Write report +work").id(1).notes(vec!["first draft".to_string()]).build(),
+        ]
+    }
+
+    fn gen_api() -> Api {
+        let confdir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4()));
+        create_dir_all(confdir.join("todolists")).unwrap();
+        for name in ["home", "work"] {
+            let path = confdir.join("todolists").join(format!("{}.json", name));
+            write(&path, "[]").unwrap();
+            save_todos(&path, &mut gen_todo()).unwrap();
+        }
+        symlink(confdir.join("todolists/work.json"), confdir.join("active_todos.json")).unwrap();
+        Api::new(&confdir)
+    }
+
+    fn saved(api: &Api, name: &str) -> Vec<Todo> {
+        load_todos(&api.confdir.join("todolists").join(format!("{}.json", name))).unwrap()
+    }
+
+    #[test]
+    fn test_list() {
+        let api = gen_api();
+
+        let (status, lists) = api.handle(&Method::Get, "/todolists", "");
+        assert_eq!(status, 200);
+        assert_eq!(lists, json!([{ "name": "home", "active": false }, { "name": "work", "active": true }]));
+
+        let (status, todos) = api.handle(&Method::Get, "/todos?filter=project%3Awork+report", "");
+        assert_eq!(status, 200);
+        assert_eq!(todos.as_array().unwrap().len(), 1);
+        assert_eq!(todos[0]["id"], 1);
+
+        assert_eq!(api.handle(&Method::Get, "/todolists/home/todos/0", "").1["subject"], "Buy milk +groceries");
+        remove_dir_all(&api.confdir).unwrap();
+    }
+
+    #[test]
+    fn test_create_edit_complete() {
+        let api = gen_api();
+
+        let (status, todo) = api.handle(&Method::Post, "/todolists/home/todos", r#"{"subject": "Call mom @phone", "due": "2030-01-31"}"#);
+        assert_eq!(status, 201);
+        assert_eq!(todo["id"], 2);
+        assert_eq!(todo["contexts"], json!(["phone"]));
+
+        let (status, todo) = api.handle(&Method::Patch, "/todolists/home/todos/2", r#"{"due": "none"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(todo["subject"], "Call mom @phone");
+        assert_eq!(todo["due"], "");
+
+        assert_eq!(api.handle(&Method::Post, "/todolists/home/todos/2/complete", "").0, 200);
+        assert_eq!(api.handle(&Method::Post, "/todolists/home/todos/2/archive", "").0, 200);
+
+        let home = saved(&api, "home");
+        let called = home.iter().find(|t| t.id() == 2).unwrap();
+        assert!(called.completed() && called.archived());
+        assert_eq!(saved(&api, "work").len(), 2);
+        remove_dir_all(&api.confdir).unwrap();
+    }
+
+    #[test]
+    fn test_notes() {
+        let api = gen_api();
+
+        assert_eq!(api.handle(&Method::Post, "/todos/1/notes", r#"{"note": "send to Ann"}"#).0, 200);
+        assert_eq!(api.handle(&Method::Put, "/todos/1/notes/0", r#"{"note": "second draft"}"#).0, 200);
+        let (status, todo) = api.handle(&Method::Delete, "/todos/1/notes/1", "");
+
+        assert_eq!(status, 200);
+        assert_eq!(todo["notes"], json!(["second draft"]));
+        assert_eq!(saved(&api, "work").iter().find(|t| t.id() == 1).unwrap().notes(), ["second draft"]);
+        remove_dir_all(&api.confdir).unwrap();
+    }
+
+    #[test]
+    fn test_errors() {
+        let api = gen_api();
+
+        assert_eq!(api.handle(&Method::Get, "/todos/9", "").0, 404);
+        assert_eq!(api.handle(&Method::Get, "/todolists/nope/todos", "").0, 404);
+        assert_eq!(api.handle(&Method::Delete, "/todos", "").0, 404);
+        assert_eq!(api.handle(&Method::Post, "/todos", "{\"subject\": 3}").0, 400);
+        assert_eq!(api.handle(&Method::Post, "/todos", r#"{"subject": "x", "due": "soon"}"#).0, 400);
+        assert_eq!(api.handle(&Method::Get, "/todos?filter=due%3A", "").0, 400);
+        assert_eq!(saved(&api, "work").len(), 2);
+        remove_dir_all(&api.confdir).unwrap();
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<Header> {
+        pairs.iter().map(|(k, v)| Header::from_bytes(*k, *v).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_check_request() {
+        let json = ("Content-Type", "application/json; charset=utf-8");
+
+        assert_eq!(check_request(&Method::Get, &headers(&[("Host", "localhost:8080")])), Ok(None));
+        assert_eq!(check_request(&Method::Post, &headers(&[("Host", "127.0.0.1:8080"), json])), Ok(None));
+        assert_eq!(check_request(&Method::Get, &headers(&[("Host", "[::1]:8080"), ("Origin", "http://localhost:3000")])), Ok(Some("http://localhost:3000".to_string())));
+
+        assert_eq!(check_request(&Method::Get, &headers(&[("Host", "evil.example:8080")])).unwrap_err().0, 403);
+        assert_eq!(check_request(&Method::Get, &headers(&[])).unwrap_err().0, 403);
+        assert_eq!(check_request(&Method::Get, &headers(&[("Host", "localhost.evil.example")])).unwrap_err().0, 403);
+        assert_eq!(check_request(&Method::Get, &headers(&[("Host", "localhost"), ("Origin", "https://evil.example")])).unwrap_err().0, 403);
+        assert_eq!(check_request(&Method::Get, &headers(&[("Host", "localhost"), ("Origin", "null")])).unwrap_err().0, 403);
+        assert_eq!(check_request(&Method::Post, &headers(&[("Host", "localhost"), ("Content-Type", "text/plain")])).unwrap_err().0, 415);
+        assert_eq!(check_request(&Method::Patch, &headers(&[("Host", "localhost")])).unwrap_err().0, 415);
+        assert!(check_request(&Method::Delete, &headers(&[("Host", "localhost")])).is_ok());
+    }
+
+    #[test]
+    fn test_cors_headers() {
+        let fields = |h: Vec<Header>| h.iter().map(|h| h.field.to_string()).collect::<Vec<String>>();
+
+        assert_eq!(fields(cors_headers("http://localhost:3000", false)), ["Access-Control-Allow-Origin", "Vary"]);
+        let preflight = cors_headers("http://localhost:3000", true);
+        assert_eq!(header(&preflight, "Access-Control-Allow-Headers"), Some("Content-Type"));
+        assert!(header(&preflight, "Access-Control-Allow-Methods").unwrap().contains("PATCH"));
+    }
+}
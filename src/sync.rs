@@ -0,0 +1,433 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{create_dir_all, read_to_string};
+use std::path::Path;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::{AppError, Todo};
+use crate::modify::find_new_id;
+use crate::todo_files::{Transaction, get_sync_snapshot, write_atomic};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Changed on both sides; the local version is kept.
+    BothChanged,
+    /// Deleted remotely but changed here; it is kept.
+    ChangedLocallyDeletedRemotely,
+    /// Deleted here but changed remotely; it is restored.
+    DeletedLocallyChangedRemotely,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    pub uuid: String,
+    pub subject: String,
+    pub kind: ConflictKind,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ConflictKind::BothChanged => write!(f, "\"{}\" was changed on both sides; kept the local version", self.subject),
+            ConflictKind::ChangedLocallyDeletedRemotely => write!(f, "\"{}\" was deleted remotely but changed here; kept it", self.subject),
+            ConflictKind::DeletedLocallyChangedRemotely => write!(f, "\"{}\" was deleted here but changed remotely; restored it", self.subject),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Merge {
+    pub todos: Vec<Todo>,
+    /// Todos changed, added or deleted remotely and taken over here.
+    pub pulled: usize,
+    /// Todos changed, added or deleted here and sent to the remote.
+    pub pushed: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Ids are local, so two versions of a todo are the same if they only
+/// differ in their id.
+pub(crate) fn same(a: &Todo, b: &Todo) -> bool {
+    Todo { id: b.id, ..a.clone() } == *b
+}
+
+/// Merges by uuid against `base`, the list as it was after the last sync.
+/// A side that left a todo as it was in `base` takes the other side's
+/// version, including its deletion.
+pub fn merge(base: &[Todo], local: &[Todo], remote: &[Todo]) -> Merge {
+    let find = |list: &[Todo], uuid: &str| list.iter().find(|t| t.uuid == uuid).cloned();
+    let mut merge = Merge::default();
+    let mut remote_only: Vec<Todo> = Vec::new();
+    let conflict = |todo: &Todo, kind| Conflict { uuid: todo.uuid.clone(), subject: todo.subject.clone(), kind };
+
+    for uuid in local.iter().chain(remote.iter()).map(|t| t.uuid.as_str()).unique() {
+        match (find(base, uuid), find(local, uuid), find(remote, uuid)) {
+            (None, Some(l), None) => {
+                merge.pushed += 1;
+                merge.todos.push(l);
+            },
+            (Some(b), Some(l), None) if same(&b, &l) => merge.pulled += 1,
+            (Some(_), Some(l), None) => {
+                merge.conflicts.push(conflict(&l, ConflictKind::ChangedLocallyDeletedRemotely));
+                merge.todos.push(l);
+            },
+            (None, None, Some(r)) => {
+                merge.pulled += 1;
+                remote_only.push(r);
+            },
+            (Some(b), None, Some(r)) if same(&b, &r) => merge.pushed += 1,
+            (Some(_), None, Some(r)) => {
+                merge.conflicts.push(conflict(&r, ConflictKind::DeletedLocallyChangedRemotely));
+                remote_only.push(r);
+            },
+            (b, Some(l), Some(r)) => {
+                let unchanged = |t: &Todo| b.as_ref().is_some_and(|b| same(b, t));
+                if same(&l, &r) {
+                    merge.todos.push(l);
+                } else if unchanged(&l) {
+                    merge.pulled += 1;
+                    merge.todos.push(Todo { id: l.id, ..r });
+                } else if unchanged(&r) {
+                    merge.pushed += 1;
+                    merge.todos.push(l);
+                } else {
+                    merge.conflicts.push(conflict(&l, ConflictKind::BothChanged));
+                    merge.todos.push(l);
+                }
+            },
+            (_, None, None) => (),
+        }
+    }
+    for todo in remote_only {
+        let id = find_new_id(&merge.todos);
+        merge.todos.push(Todo { id, ..todo });
+    }
+    merge
+}
+
+/// The list as it was after the last sync and the endpoint it was synced
+/// with.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    endpoint: String,
+    todos: Vec<Todo>,
+}
+
+/// The base for merging with `endpoint`. A base from another endpoint, or
+/// from a version that did not record one, would make every todo missing
+/// there look deleted, so the merge then starts without one.
+fn load_base(snapshot: &Path, endpoint: &str) -> Result<Vec<Todo>, Box<dyn Error>> {
+    if !snapshot.exists() {
+        return Ok(Vec::new());
+    }
+    match serde_json::from_str::<Snapshot>(&read_to_string(snapshot)?) {
+        Ok(snapshot) if snapshot.endpoint == endpoint => Ok(snapshot.todos),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Gives every todo without a uuid, or with one an earlier todo already
+/// has, a new one, as the merge tells todos apart by uuid alone.
+fn assign_uuids(todos: &mut [Todo]) {
+    let mut seen: Vec<String> = Vec::new();
+    for todo in todos.iter_mut() {
+        if todo.uuid.is_empty() || seen.contains(&todo.uuid) {
+            todo.uuid = Uuid::new_v4().to_string();
+        }
+        seen.push(todo.uuid.clone());
+    }
+}
+
+/// The remote list and its ETag, if the endpoint sends one.
+fn fetch(endpoint: &str) -> Result<(Vec<Todo>, Option<String>), Box<dyn Error>> {
+    let mut response = ureq::get(endpoint).call()?;
+    let etag = response.headers().get("ETag").and_then(|v| v.to_str().ok()).map(|v| v.to_string());
+    let body = response.body_mut().read_to_string()?;
+    if body.trim().is_empty() {
+        return Ok((Vec::new(), etag));
+    }
+    Ok((serde_json::from_str(&body)?, etag))
+}
+
+/// Sends the merged list, failing with `RemoteChangedError` if the remote
+/// changed since it was fetched.
+fn push(endpoint: &str, etag: Option<&str>, merged: &str) -> Result<(), Box<dyn Error>> {
+    let mut request = ureq::put(endpoint).header("Content-Type", "application/json");
+    if let Some(etag) = etag {
+        request = request.header("If-Match", etag);
+    }
+    match request.send(merged) {
+        Err(ureq::Error::StatusCode(412)) => Err(Box::new(AppError::RemoteChangedError(endpoint.to_string()))),
+        r => Ok(r.map(|_| ())?),
+    }
+}
+
+/// Pulls the remote list, merges it with the local one and pushes the
+/// result back. Nothing is saved locally unless the push succeeded, and
+/// the base for the next merge is only updated once the local list is.
+pub fn sync(path: &Path, endpoint: &str) -> Result<Merge, Box<dyn Error>> {
+    let mut tx = Transaction::open(path)?;
+    let snapshot = get_sync_snapshot(tx.path())?;
+    let base = load_base(&snapshot, endpoint)?;
+    let (mut remote, etag) = fetch(endpoint)?;
+    assign_uuids(&mut tx.todos);
+    assign_uuids(&mut remote);
+
+    let merge = merge(&base, &tx.todos, &remote);
+    let merged = serde_json::to_string(&merge.todos)?;
+    push(endpoint, etag.as_deref(), &merged)?;
+
+    tx.todos = merge.todos.clone();
+    tx.commit(Some(&format!("sync with {}", endpoint)))?;
+    if let Some(dir) = snapshot.parent() {
+        create_dir_all(dir)?;
+    }
+    let base = Snapshot { endpoint: endpoint.to_string(), todos: merge.todos.clone() };
+    write_atomic(&snapshot, &serde_json::to_string(&base)?)?;
+    Ok(merge)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::collections::hash_map::DefaultHasher;
+    use std::fs::{read_to_string, remove_dir_all, write};
+    use std::hash::{Hash, Hasher};
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use tiny_http::{Method, Response, Server};
+    use uuid::Uuid;
+    use crate::todo_files::{load_todos, save_todos};
+
+    fn etag(todos: &[Todo]) -> String {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(todos).unwrap().hash(&mut hasher);
+        format!("\"{:x}\"", hasher.finish())
+    }
+
+    /// A stand-in for the sync service: GET returns the stored list with an
+    /// ETag and PUT replaces it if its If-Match still matches.
+    struct MockServer {
+        server: Arc<Server>,
+        todos: Arc<Mutex<Vec<Todo>>>,
+        /// A subject the next GET renames the first todo to right after
+        /// answering, like an edit made while a sync is running.
+        edit_after_get: Arc<Mutex<Option<String>>>,
+        endpoint: String,
+    }
+
+    impl MockServer {
+        fn start() -> MockServer {
+            let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+            let endpoint = format!("http://{}/todos", server.server_addr().to_ip().unwrap());
+            let todos: Arc<Mutex<Vec<Todo>>> = Arc::default();
+            let edit_after_get: Arc<Mutex<Option<String>>> = Arc::default();
+            let (s, t, e) = (server.clone(), todos.clone(), edit_after_get.clone());
+            thread::spawn(move || {
+                for mut request in s.incoming_requests() {
+                    let mut body = String::new();
+                    request.as_reader().read_to_string(&mut body).unwrap();
+                    let if_match = request.headers().iter().find(|h| h.field.equiv("If-Match")).map(|h| h.value.to_string());
+                    let mut todos = t.lock().unwrap();
+                    let response = match request.method() {
+                        Method::Get => {
+                            let response = Response::from_string(serde_json::to_string(&*todos).unwrap())
+                                .with_header(format!("ETag: {}", etag(&todos)).parse::<tiny_http::Header>().unwrap());
+                            if let Some(subject) = e.lock().unwrap().take() {
+                                todos[0].subject = subject;
+                            }
+                            response
+                        },
+                        Method::Put if if_match.is_some_and(|m| m != etag(&todos)) => Response::from_string("").with_status_code(412),
+                        Method::Put => {
+                            *todos = serde_json::from_str(&body).unwrap();
+                            Response::from_string("")
+                        },
+                        _ => Response::from_string("").with_status_code(405),
+                    };
+                    request.respond(response).unwrap();
+                }
+            });
+            MockServer { server, todos, edit_after_get, endpoint }
+        }
+
+        fn todo(&self, uuid: &str) -> Todo {
+            self.todos.lock().unwrap().iter().find(|t| t.uuid == uuid).unwrap().clone()
+        }
+    }
+
+    impl Drop for MockServer {
+        fn drop(&mut self) {
+            self.server.unblock();
+        }
+    }
+
+    fn gen_todo() -> Vec<Todo> {
+        vec![
+            Todo::builder("Buy milk").id(0).uuid("uuid-0").build(),
+            Todo::builder("Write report").id(1).uuid("uuid-1").build(),
+            Todo::builder("Call mom").id(2).uuid("uuid-2").build(),
+        ]
+    }
+
+    fn changed(todo: &Todo, subject: &str) -> Todo {
+        Todo { subject: subject.to_string(), ..todo.clone() }
+    }
+
+    fn gen_todolist() -> PathBuf {
+        let dir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4())).join("todolists");
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("list.json");
+        write(&path, "[]").unwrap();
+        save_todos(&path, &mut gen_todo()).unwrap();
+        path
+    }
+
+    fn local(path: &Path, uuid: &str) -> Option<Todo> {
+        load_todos(path).unwrap().into_iter().find(|t| t.uuid == uuid)
+    }
+
+    #[test]
+    fn test_merge_one_sided() {
+        let base = gen_todo();
+        let local = vec![changed(&base[0], "Buy oat milk"), base[1].clone()];
+        let remote = vec![base[0].clone(), changed(&base[1], "Write the report"), base[2].clone(), Todo::builder("New").id(0).uuid("uuid-3").build()];
+
+        let m = merge(&base, &local, &remote);
+
+        let subjects: Vec<(u64, &str)> = m.todos.iter().map(|t| (t.id, t.subject.as_str())).collect();
+        assert_eq!(subjects, [(0, "Buy oat milk"), (1, "Write the report"), (2, "New")]);
+        assert_eq!((m.pulled, m.pushed), (2, 2));
+        assert!(m.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let base = gen_todo();
+        let local = vec![changed(&base[0], "Buy oat milk"), changed(&base[1], "Write report today")];
+        let remote = vec![changed(&base[0], "Buy soy milk"), changed(&base[2], "Call mom tonight")];
+
+        let m = merge(&base, &local, &remote);
+
+        let kinds: Vec<(&str, ConflictKind)> = m.conflicts.iter().map(|c| (c.uuid.as_str(), c.kind)).collect();
+        assert_eq!(kinds, [
+            ("uuid-0", ConflictKind::BothChanged),
+            ("uuid-1", ConflictKind::ChangedLocallyDeletedRemotely),
+            ("uuid-2", ConflictKind::DeletedLocallyChangedRemotely),
+        ]);
+        let subjects: Vec<&str> = m.todos.iter().map(|t| t.subject.as_str()).collect();
+        assert_eq!(subjects, ["Buy oat milk", "Write report today", "Call mom tonight"]);
+    }
+
+    #[test]
+    fn test_sync_with_mock_server() {
+        let mock = MockServer::start();
+        let path = gen_todolist();
+
+        let first = sync(&path, &mock.endpoint).unwrap();
+        assert_eq!((first.pushed, first.pulled), (3, 0));
+        assert_eq!(mock.todos.lock().unwrap().len(), 3);
+
+        {
+            let mut remote = mock.todos.lock().unwrap();
+            remote.retain(|t| t.uuid != "uuid-2");
+            remote.iter_mut().filter(|t| t.uuid == "uuid-0").for_each(|t| t.subject = "Buy oat milk".to_string());
+        }
+        let mut tx = Transaction::open(&path).unwrap();
+        tx.todos.iter_mut().filter(|t| t.uuid == "uuid-1").for_each(|t| t.subject = "Write report today".to_string());
+        tx.commit(None).unwrap();
+
+        let second = sync(&path, &mock.endpoint).unwrap();
+        assert_eq!((second.pushed, second.pulled), (1, 2));
+        assert!(second.conflicts.is_empty());
+        assert_eq!(local(&path, "uuid-0").unwrap().subject, "Buy oat milk");
+        assert_eq!(local(&path, "uuid-2"), None);
+        assert_eq!(mock.todo("uuid-1").subject, "Write report today");
+
+        mock.todos.lock().unwrap().iter_mut().filter(|t| t.uuid == "uuid-0").for_each(|t| t.subject = "Buy soy milk".to_string());
+        let mut tx = Transaction::open(&path).unwrap();
+        tx.todos.iter_mut().filter(|t| t.uuid == "uuid-0").for_each(|t| t.subject = "Buy rice milk".to_string());
+        tx.commit(None).unwrap();
+
+        let third = sync(&path, &mock.endpoint).unwrap();
+        assert_eq!(third.conflicts.len(), 1);
+        assert_eq!(third.conflicts[0].to_string(), "\"Buy rice milk\" was changed on both sides; kept the local version");
+        assert_eq!(mock.todo("uuid-0").subject, "Buy rice milk");
+
+        remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_sync_with_two_servers() {
+        let first = MockServer::start();
+        let second = MockServer::start();
+        let path = gen_todolist();
+        sync(&path, &first.endpoint).unwrap();
+
+        let m = sync(&path, &second.endpoint).unwrap();
+
+        assert_eq!((m.pushed, m.pulled), (3, 0));
+        assert_eq!(load_todos(&path).unwrap().len(), 3);
+        assert_eq!(second.todos.lock().unwrap().len(), 3);
+
+        second.todos.lock().unwrap().retain(|t| t.uuid != "uuid-2");
+        let m = sync(&path, &first.endpoint).unwrap();
+
+        assert_eq!((m.pushed, m.pulled), (0, 0));
+        assert_eq!(load_todos(&path).unwrap().len(), 3);
+
+        remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_sync_without_uuids() {
+        let mock = MockServer::start();
+        let path = gen_todolist();
+        let mut tx = Transaction::open(&path).unwrap();
+        tx.todos.iter_mut().for_each(|t| t.uuid = "".to_string());
+        tx.todos.push(Todo::builder("Water plants").id(3).uuid("").build());
+        tx.commit(None).unwrap();
+        mock.todos.lock().unwrap().extend([
+            Todo::builder("Pay rent").id(0).uuid("dup").build(),
+            Todo::builder("Pay bills").id(1).uuid("dup").build(),
+        ]);
+
+        let m = sync(&path, &mock.endpoint).unwrap();
+
+        assert_eq!((m.pushed, m.pulled), (4, 2));
+        let todos = load_todos(&path).unwrap();
+        assert_eq!(todos.len(), 6);
+        assert!(todos.iter().all(|t| !t.uuid.is_empty()));
+        assert_eq!(todos.iter().map(|t| &t.uuid).unique().count(), 6);
+        assert_eq!(mock.todos.lock().unwrap().len(), 6);
+
+        remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_remote_changed_during_sync() {
+        let mock = MockServer::start();
+        let path = gen_todolist();
+        sync(&path, &mock.endpoint).unwrap();
+        let snapshot = get_sync_snapshot(&path).unwrap();
+        let base = read_to_string(&snapshot).unwrap();
+
+        let mut tx = Transaction::open(&path).unwrap();
+        tx.todos.iter_mut().for_each(|t| t.subject = format!("{} today", t.subject));
+        tx.commit(None).unwrap();
+        *mock.edit_after_get.lock().unwrap() = Some("Edited remotely".to_string());
+        let local_before = load_todos(&path).unwrap();
+
+        let r = sync(&path, &mock.endpoint);
+
+        assert!(matches!(r.unwrap_err().downcast_ref::<AppError>(), Some(AppError::RemoteChangedError(_))));
+        assert_eq!(load_todos(&path).unwrap(), local_before);
+        assert_eq!(read_to_string(&snapshot).unwrap(), base);
+        assert!(mock.todos.lock().unwrap().iter().any(|t| t.subject == "Edited remotely"));
+
+        remove_dir_all(path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+}
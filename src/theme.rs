@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+use std::sync::OnceLock;
+use colored::{Color, Colorize};
+use serde::Deserialize;
+use crate::{AppError, ColorChoice, Column};
+
+pub const BUILTIN: [&str; 3] = ["default", "light", "mono"];
+
+/// Colors and styles for list output. Each is a color name like "red" or
+/// "bright white", or a hex color like "#ff8800", optionally preceded by
+/// bold, dimmed, italic, underline or reversed. "" leaves text as it is.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub id: String,
+    pub completed: String,
+    pub due: String,
+    pub overdue: String,
+    pub status: String,
+    pub subject: String,
+    pub project: String,
+    pub context: String,
+    /// Applied to every cell of a priority todo.
+    pub priority: String,
+    /// Today in `tlist cal`.
+    pub today: String,
+    /// Colors for single projects, by name without the '+'.
+    pub projects: HashMap<String, String>,
+    /// Colors for single contexts, by name without the '@'.
+    pub contexts: HashMap<String, String>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            id: "yellow".to_string(),
+            completed: "blue".to_string(),
+            due: "yellow".to_string(),
+            overdue: "red".to_string(),
+            status: "red".to_string(),
+            subject: "bright white".to_string(),
+            project: "purple".to_string(),
+            context: "green".to_string(),
+            priority: "bold".to_string(),
+            today: "reversed".to_string(),
+            projects: HashMap::new(),
+            contexts: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Style {
+    color: Option<Color>,
+    bold: bool,
+    dimmed: bool,
+    italic: bool,
+    underline: bool,
+    reversed: bool,
+}
+
+impl TryFrom<&str> for Style {
+    type Error = AppError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let invalid = || AppError::InvalidConfigError(format!("invalid style \"{}\": expected a color like red, bright white or #ff8800, optionally after bold, dimmed, italic, underline or reversed", value));
+        let mut style = Style::default();
+        let mut words = value.split_whitespace().peekable();
+        while let Some(word) = words.peek() {
+            match *word {
+                "bold" => style.bold = true,
+                "dimmed" => style.dimmed = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "reversed" => style.reversed = true,
+                _ => break,
+            }
+            words.next();
+        }
+        let color = words.collect::<Vec<&str>>().join(" ");
+        style.color = match color.strip_prefix('#') {
+            _ if color.is_empty() => None,
+            Some(hex) if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid());
+                Some(Color::TrueColor { r: channel(0)?, g: channel(2)?, b: channel(4)? })
+            },
+            Some(_) => return Err(invalid()),
+            None => Some(color.parse().map_err(|_| invalid())?),
+        };
+        Ok(style)
+    }
+}
+
+impl Style {
+    fn parse(value: &str) -> Style {
+        Style::try_from(value).unwrap_or_default()
+    }
+
+    /// Adds `other`'s color and modifiers on top of these.
+    fn and(self, other: Style) -> Style {
+        Style {
+            color: other.color.or(self.color),
+            bold: self.bold || other.bold,
+            dimmed: self.dimmed || other.dimmed,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+            reversed: self.reversed || other.reversed,
+        }
+    }
+
+    pub fn paint(&self, text: &str) -> String {
+        let mut s = text.normal();
+        if let Some(color) = self.color {
+            s = s.color(color);
+        }
+        if self.bold { s = s.bold(); }
+        if self.dimmed { s = s.dimmed(); }
+        if self.italic { s = s.italic(); }
+        if self.underline { s = s.underline(); }
+        if self.reversed { s = s.reversed(); }
+        s.to_string()
+    }
+
+    /// The style for the TUI; none at all when output is not colored.
+    pub fn tui(&self) -> ratatui::style::Style {
+        if colored::control::SHOULD_COLORIZE.should_colorize() { self.ratatui() } else { ratatui::style::Style::new() }
+    }
+
+    fn ratatui(&self) -> ratatui::style::Style {
+        use ratatui::style::{Color as TuiColor, Modifier};
+        let mut style = ratatui::style::Style::new();
+        if let Some(color) = self.color {
+            style = style.fg(match color {
+                Color::Black => TuiColor::Black,
+                Color::Red => TuiColor::Red,
+                Color::Green => TuiColor::Green,
+                Color::Yellow => TuiColor::Yellow,
+                Color::Blue => TuiColor::Blue,
+                Color::Magenta => TuiColor::Magenta,
+                Color::Cyan => TuiColor::Cyan,
+                Color::White => TuiColor::Gray,
+                Color::BrightBlack => TuiColor::DarkGray,
+                Color::BrightRed => TuiColor::LightRed,
+                Color::BrightGreen => TuiColor::LightGreen,
+                Color::BrightYellow => TuiColor::LightYellow,
+                Color::BrightBlue => TuiColor::LightBlue,
+                Color::BrightMagenta => TuiColor::LightMagenta,
+                Color::BrightCyan => TuiColor::LightCyan,
+                Color::BrightWhite => TuiColor::White,
+                Color::TrueColor { r, g, b } => TuiColor::Rgb(r, g, b),
+            });
+        }
+        for (on, modifier) in [(self.bold, Modifier::BOLD), (self.dimmed, Modifier::DIM), (self.italic, Modifier::ITALIC), (self.underline, Modifier::UNDERLINED), (self.reversed, Modifier::REVERSED)] {
+            if on {
+                style = style.add_modifier(modifier);
+            }
+        }
+        style
+    }
+
+    /// The style as a table column color.
+    pub fn column(&self) -> tabled::settings::Color {
+        let mut set: Vec<String> = Vec::new();
+        let mut reset: Vec<&str> = Vec::new();
+        for (on, code, off) in [(self.bold, "1", "22"), (self.dimmed, "2", "22"), (self.italic, "3", "23"), (self.underline, "4", "24"), (self.reversed, "7", "27")] {
+            if on {
+                set.push(code.to_string());
+                reset.push(off);
+            }
+        }
+        if let Some(color) = self.color {
+            set.push(color.to_fg_str().to_string());
+            reset.push("39");
+        }
+        if set.is_empty() {
+            return tabled::settings::Color::empty();
+        }
+        reset.dedup();
+        tabled::settings::Color::new(format!("\u{1b}[{}m", set.join(";")), format!("\u{1b}[{}m", reset.join(";")))
+    }
+}
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Theme> {
+        match name {
+            "default" => Some(Theme::default()),
+            "light" => Some(Theme {
+                id: "blue".to_string(),
+                due: "magenta".to_string(),
+                overdue: "bold red".to_string(),
+                subject: "black".to_string(),
+                ..Theme::default()
+            }),
+            "mono" => Some(Theme {
+                id: "".to_string(),
+                completed: "".to_string(),
+                due: "".to_string(),
+                overdue: "reversed".to_string(),
+                status: "".to_string(),
+                subject: "".to_string(),
+                project: "underline".to_string(),
+                context: "italic".to_string(),
+                ..Theme::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// `themes/<name>.toml` in `confdir`, or else the built-in theme of that
+    /// name. A theme file only needs the settings it changes from the
+    /// default theme.
+    pub fn load(confdir: &Path, name: &str) -> Result<Theme, Box<dyn Error>> {
+        let path = confdir.join("themes").join(format!("{}.toml", name));
+        if !path.exists() {
+            return Theme::builtin(name).ok_or_else(|| Box::new(unknown_theme(confdir, name)) as Box<dyn Error>);
+        }
+        let theme: Theme = toml::from_str(&read_to_string(&path)?)
+            .map_err(|e| AppError::InvalidConfigError(format!("{}: {}", path.display(), e.message())))?;
+        theme.check().map_err(|e| match e {
+            AppError::InvalidConfigError(m) => AppError::InvalidConfigError(format!("{}: {}", path.display(), m)),
+            e => e,
+        })?;
+        Ok(theme)
+    }
+
+    fn check(&self) -> Result<(), AppError> {
+        let styles = [&self.id, &self.completed, &self.due, &self.overdue, &self.status, &self.subject, &self.project, &self.context, &self.priority, &self.today];
+        for style in styles.into_iter().chain(self.projects.values()).chain(self.contexts.values()) {
+            Style::try_from(style.as_str())?;
+        }
+        Ok(())
+    }
+
+    /// Projects and contexts are colored word by word instead.
+    fn column_style(&self, column: Column) -> Style {
+        let style = match column {
+            Column::Id | Column::Uuid => &self.id,
+            Column::Done | Column::CompletedDate => &self.completed,
+            Column::Due | Column::Age => &self.due,
+            Column::Status => &self.status,
+            Column::Subject => &self.subject,
+            Column::Projects | Column::Contexts => return Style::default(),
+        };
+        Style::parse(style)
+    }
+
+    /// The color of a table column.
+    pub fn column(&self, column: Column) -> tabled::settings::Color {
+        self.column_style(column).column()
+    }
+
+    /// A cell's text, styled for a priority todo if it is one.
+    pub fn cell(&self, text: String, is_priority: bool) -> String {
+        if is_priority { Style::parse(&self.priority).paint(&text) } else { text }
+    }
+
+    /// The TUI style of a cell in `column`, like in `tlist list`. Overdue
+    /// due dates, priority todos and subject words follow the same rules.
+    pub fn tui_cell(&self, column: Column, is_priority: bool, overdue: bool) -> ratatui::style::Style {
+        let mut style = self.column_style(column);
+        if overdue {
+            style = style.and(Style::parse(&self.overdue));
+        }
+        if is_priority {
+            style = style.and(Style::parse(&self.priority));
+        }
+        style.tui()
+    }
+
+    pub fn tui_word(&self, word: &str, is_priority: bool) -> ratatui::style::Style {
+        let mut style = self.word_style(word).unwrap_or_else(|| self.column_style(Column::Subject));
+        if is_priority {
+            style = style.and(Style::parse(&self.priority));
+        }
+        style.tui()
+    }
+
+    pub fn today(&self, text: &str) -> String {
+        Style::parse(&self.today).paint(text)
+    }
+
+    pub fn due(&self, text: &str, overdue: bool) -> String {
+        if overdue { Style::parse(&self.overdue).paint(text) } else { text.to_string() }
+    }
+
+    /// The style of a project or a context in a subject.
+    fn word_style(&self, word: &str) -> Option<Style> {
+        let (general, own) = match word.split_at_checked(1) {
+            Some(("+", name)) => (&self.project, self.projects.get(name)),
+            Some(("@", name)) => (&self.context, self.contexts.get(name)),
+            _ => return None,
+        };
+        let style = Style::parse(general);
+        Some(own.map(|s| style.and(Style::parse(s))).unwrap_or(style))
+    }
+
+    /// A word of a subject, colored if it is a project or a context.
+    pub fn word(&self, word: &str) -> String {
+        match self.word_style(word) {
+            Some(style) => style.paint(word),
+            None => word.to_string(),
+        }
+    }
+}
+
+fn unknown_theme(confdir: &Path, name: &str) -> AppError {
+    AppError::InvalidConfigError(format!("unknown theme \"{}\"; expected one of {}", name, names(confdir).join(", ")))
+}
+
+/// The built-in themes and those in the config dir.
+pub fn names(confdir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = BUILTIN.iter().map(|n| n.to_string()).collect();
+    if let Ok(entries) = read_dir(confdir.join("themes")) {
+        entries.filter_map(|e| e.ok()?.path().file_stem()?.to_str().map(str::to_string))
+            .for_each(|n| names.push(n));
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn exists(confdir: &Path, name: &str) -> Result<(), AppError> {
+    if names(confdir).iter().any(|n| n == name) { Ok(()) } else { Err(unknown_theme(confdir, name)) }
+}
+
+/// Whether output is colored: `always` and `never` decide, `auto` colors
+/// unless NO_COLOR is set or stdout is not a terminal.
+pub fn use_color(choice: ColorChoice, no_color: bool, is_terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => !no_color && is_terminal,
+    }
+}
+
+static CURRENT: OnceLock<Theme> = OnceLock::new();
+
+pub fn init(theme: Theme) {
+    let _ = CURRENT.set(theme);
+}
+
+pub fn current() -> &'static Theme {
+    CURRENT.get_or_init(Theme::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_style() {
+        assert_eq!(Style::try_from("bold bright white"), Ok(Style { color: Some(Color::BrightWhite), bold: true, ..Style::default() }));
+        assert_eq!(Style::try_from("#ff8800"), Ok(Style { color: Some(Color::TrueColor { r: 255, g: 136, b: 0 }), ..Style::default() }));
+        assert_eq!(Style::try_from(""), Ok(Style::default()));
+        assert!(Style::try_from("bold brown").is_err());
+        assert!(Style::try_from("#ff88").is_err());
+        assert!(Style::try_from("#éé00").is_err());
+        assert!(Style::try_from("#+f+f+f").is_err());
+        assert!(Style::try_from("red bold").is_err());
+    }
+
+    #[test]
+    fn test_ratatui() {
+        use ratatui::style::{Color as TuiColor, Modifier};
+        let style = Style::parse("bold underline bright white").ratatui();
+        assert_eq!(style, ratatui::style::Style::new().fg(TuiColor::White).add_modifier(Modifier::BOLD | Modifier::UNDERLINED));
+        assert_eq!(Style::parse("#ff8800").ratatui(), ratatui::style::Style::new().fg(TuiColor::Rgb(255, 136, 0)));
+        assert_eq!(Style::parse("").ratatui(), ratatui::style::Style::new());
+    }
+
+    fn ansi(color: &tabled::settings::Color) -> (&str, &str) {
+        (color.get_prefix(), color.get_suffix())
+    }
+
+    #[test]
+    fn test_columns() {
+        let theme = Theme::default();
+        assert_eq!(ansi(&theme.column(Column::Id)), ("\u{1b}[33m", "\u{1b}[39m"));
+        assert_eq!(ansi(&theme.column(Column::Subject)), ("\u{1b}[97m", "\u{1b}[39m"));
+        assert_eq!(ansi(&theme.column(Column::Projects)), ("", ""));
+        let theme = Theme { id: "bold underline bright blue".to_string(), ..Theme::default() };
+        assert_eq!(ansi(&theme.column(Column::Id)), ("\u{1b}[1;4;94m", "\u{1b}[22;24;39m"));
+        assert_eq!(ansi(&Theme::builtin("mono").unwrap().column(Column::Id)), ("", ""));
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4()));
+        create_dir_all(dir.join("themes")).unwrap();
+        write(dir.join("themes/ocean.toml"), "subject = \"cyan\"\n\n[projects]\nwork = \"bold blue\"\n").unwrap();
+        write(dir.join("themes/broken.toml"), "subject = \"sea green\"\n").unwrap();
+        write(dir.join("themes/typo.toml"), "subjet = \"cyan\"\n").unwrap();
+
+        let ocean = Theme::load(&dir, "ocean").unwrap();
+        assert_eq!(ocean.subject, "cyan");
+        assert_eq!(ocean.due, "yellow");
+        assert_eq!(ocean.projects["work"], "bold blue");
+        assert_eq!(Theme::load(&dir, "light").unwrap(), Theme::builtin("light").unwrap());
+        assert!(Theme::load(&dir, "broken").is_err());
+        assert!(Theme::load(&dir, "typo").is_err());
+        assert!(Theme::load(&dir, "nope").unwrap_err().to_string().contains("broken, default, light, mono, ocean, typo"));
+        assert!(exists(&dir, "ocean").is_ok());
+        assert!(exists(&dir, "nope").is_err());
+        remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_use_color() {
+        assert!(use_color(ColorChoice::Always, true, false));
+        assert!(!use_color(ColorChoice::Never, false, true));
+        assert!(use_color(ColorChoice::Auto, false, true));
+        assert!(!use_color(ColorChoice::Auto, true, true));
+        assert!(!use_color(ColorChoice::Auto, false, false));
+    }
+}
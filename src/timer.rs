@@ -0,0 +1,180 @@
+use chrono::{DateTime, Days, Local, Months, NaiveDate, NaiveTime, TimeDelta};
+use serde::{Deserialize, Serialize};
+use tabled::{builder::Builder, settings::style::Style};
+use crate::{Todo, AppError, GroupOption, SerdeDate, SerdeDateTime};
+use crate::list::todo_grouping;
+use crate::serde_date::{is_month_day, is_weekday, month_first_locale};
+use crate::modify::find_todo_index;
+
+/// One tracked interval. `end` is empty while the timer is running.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct TimeEntry {
+    start: SerdeDateTime,
+    end: SerdeDateTime,
+}
+
+impl TimeEntry {
+    pub fn start(&self) -> &SerdeDateTime {
+        &self.start
+    }
+
+    pub fn end(&self) -> &SerdeDateTime {
+        &self.end
+    }
+
+    /// The part of this interval that falls after `since`, counting a
+    /// running timer up to `now`.
+    fn duration_since(&self, since: DateTime<Local>, now: DateTime<Local>) -> TimeDelta {
+        let Some(start) = self.start.date() else { return TimeDelta::zero() };
+        let end = self.end.date().unwrap_or(now);
+        (end - start.max(since)).max(TimeDelta::zero())
+    }
+}
+
+/// The id of the todo whose timer is running, if any.
+pub fn running(todos: &Vec<Todo>) -> Option<u64> {
+    todos.iter().find(|t| t.time_entries.iter().any(|e| e.start.date().is_some() && e.end.date().is_none())).map(|t| t.id)
+}
+
+/// Starts a timer on `id`. Only one timer may run per todolist.
+pub fn start(todos: &mut Vec<Todo>, id: u64, now: DateTime<Local>) -> Result<(), AppError> {
+    let i: usize = find_todo_index(todos, id)?;
+    if let Some(running) = running(todos) {
+        return Err(AppError::TimerRunningError(running));
+    }
+    todos[i].time_entries.push(TimeEntry { start: SerdeDateTime::from(now), end: SerdeDateTime::new_empty() });
+    Ok(())
+}
+
+/// Stops the running timer, returning the todo's id and the interval's length.
+pub fn stop(todos: &mut Vec<Todo>, now: DateTime<Local>) -> Result<(u64, TimeDelta), AppError> {
+    let id = running(todos).ok_or(AppError::NoTimerRunningError)?;
+    let i: usize = find_todo_index(todos, id)?;
+    let entry = todos[i].time_entries.iter_mut().find(|e| e.end.date().is_none()).ok_or(AppError::NoTimerRunningError)?;
+    entry.end = SerdeDateTime::from(now);
+    let length = entry.duration_since(DateTime::<Local>::MIN_UTC.into(), now);
+    Ok((id, length))
+}
+
+/// A date in the past for `--since`: "nov28" and "mon" mean the most recent
+/// one rather than the next.
+pub fn parse_since(since: &str, today: NaiveDate) -> Result<NaiveDate, AppError> {
+    let invalid = || AppError::InvalidDateError(since.to_string());
+    let date = SerdeDate::parse_at(since, today, month_first_locale())?.date().ok_or_else(invalid)?;
+    if date <= today {
+        Ok(date)
+    } else if is_weekday(since) {
+        Ok(date - Days::new(7))
+    } else if is_month_day(since) {
+        date.checked_sub_months(Months::new(12)).ok_or_else(invalid)
+    } else {
+        Ok(date)
+    }
+}
+
+/// Hours tracked since local midnight on `since`, per group, sorted by
+/// group title.
+pub fn time_report(todos: &Vec<Todo>, grouping: GroupOption, since: Option<NaiveDate>, now: DateTime<Local>) -> Vec<(String, f64)> {
+    let since = since
+        .and_then(|d| d.and_time(NaiveTime::MIN).and_local_timezone(Local).earliest())
+        .unwrap_or(DateTime::<Local>::MIN_UTC.into());
+    let mut report: Vec<(String, f64)> = todo_grouping(todos, Some(grouping))
+        .into_iter()
+        .map(|(title, group)| {
+            let total: TimeDelta = group.iter()
+                .flat_map(|t| t.time_entries.iter())
+                .map(|e| e.duration_since(since, now))
+                .sum();
+            (title.to_string(), total.num_seconds() as f64 / 3600.0)
+        })
+        .filter(|(_, hours)| *hours > 0.0)
+        .collect();
+    report.sort_by(|a, b| a.0.cmp(&b.0));
+    report
+}
+
+pub fn print_time_report(todos: &Vec<Todo>, groupings: &[GroupOption], since: Option<NaiveDate>) {
+    for grouping in groupings {
+        let report = time_report(todos, *grouping, since, Local::now());
+        let label = match grouping {
+            GroupOption::Project => "Project",
+            GroupOption::Context => "Context",
+            GroupOption::Status => "Status",
+        };
+        if report.is_empty() {
+            println!("{}: no time tracked", label);
+            continue;
+        }
+        let mut builder = Builder::default();
+        builder.push_record([label.to_string(), "Hours".to_string()]);
+        for (title, hours) in report {
+            builder.push_record([title, format!("{:.2}", hours)]);
+        }
+        let mut table = builder.build();
+        table.with(Style::blank());
+        println!("{}", table);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2025, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn gen_todo() -> Vec<Todo> {
+        vec![
+            Todo::builder("invoice +acme @office").id(0).build(),
+            Todo::builder("fix bug +acme +beta").id(1).build(),
+        ]
+    }
+
+    #[test]
+    fn test_one_timer() {
+        let mut todos = gen_todo();
+
+        assert_eq!(start(&mut todos, 0, at(1, 9)), Ok(()));
+        assert_eq!(start(&mut todos, 1, at(1, 10)), Err(AppError::TimerRunningError(0)));
+        assert_eq!(running(&todos), Some(0));
+        assert_eq!(stop(&mut todos, at(1, 11)), Ok((0, TimeDelta::hours(2))));
+        assert_eq!(stop(&mut todos, at(1, 12)), Err(AppError::NoTimerRunningError));
+        assert_eq!(start(&mut todos, 1, at(1, 12)), Ok(()));
+    }
+
+    #[test]
+    fn test_report() {
+        let mut todos = gen_todo();
+        assert!(start(&mut todos, 0, at(1, 9)).is_ok());
+        assert!(stop(&mut todos, at(1, 11)).is_ok());
+        assert!(start(&mut todos, 1, at(2, 9)).is_ok());
+        assert!(stop(&mut todos, at(2, 10)).is_ok());
+        assert!(start(&mut todos, 1, at(3, 9)).is_ok());
+
+        let projects = time_report(&todos, GroupOption::Project, None, at(3, 9) + TimeDelta::minutes(30));
+        assert_eq!(projects, vec![("acme".to_string(), 3.5), ("beta".to_string(), 1.5)]);
+
+        let contexts = time_report(&todos, GroupOption::Context, NaiveDate::from_ymd_opt(2025, 1, 2), at(4, 0));
+        assert_eq!(contexts, vec![("No contexts".to_string(), 16.0)]);
+    }
+
+    #[test]
+    fn test_parse_since() {
+        let today = NaiveDate::from_ymd_opt(2025, 1, 15).unwrap();
+
+        assert_eq!(parse_since("2025-01-01", today), Ok(NaiveDate::from_ymd_opt(2025, 1, 1).unwrap()));
+        assert_eq!(parse_since("yesterday", today), Ok(NaiveDate::from_ymd_opt(2025, 1, 14).unwrap()));
+        assert_eq!(parse_since("wed", today), Ok(today));
+        assert_eq!(parse_since("mon", today), Ok(NaiveDate::from_ymd_opt(2025, 1, 13).unwrap()));
+        assert_eq!(parse_since("fri", today), Ok(NaiveDate::from_ymd_opt(2025, 1, 10).unwrap()));
+        assert_eq!(parse_since("tues", today), Ok(NaiveDate::from_ymd_opt(2025, 1, 14).unwrap()));
+        assert_eq!(parse_since("thur", today), Ok(NaiveDate::from_ymd_opt(2025, 1, 9).unwrap()));
+        assert_eq!(parse_since("jan10", today), Ok(NaiveDate::from_ymd_opt(2025, 1, 10).unwrap()));
+        assert_eq!(parse_since("jan20", today), Ok(NaiveDate::from_ymd_opt(2024, 1, 20).unwrap()));
+        assert_eq!(parse_since("dec20", today), Ok(NaiveDate::from_ymd_opt(2024, 12, 20).unwrap()));
+        assert_eq!(parse_since("20 dec", today), Ok(NaiveDate::from_ymd_opt(2024, 12, 20).unwrap()));
+        assert!(parse_since("soon", today).is_err());
+    }
+}
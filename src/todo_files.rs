@@ -1,10 +1,14 @@
 use xdir::config;
-use std::fs::{create_dir, read_dir, File, read_link, remove_file, write, remove_dir_all};
-use std::path::PathBuf;
+use std::fs::{create_dir, read_dir, File, OpenOptions, read_link, remove_file, write, remove_dir_all, read_to_string, rename, canonicalize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::error::Error;
 use std::os::unix::fs::symlink;
-use crate::AppError;
+use itertools::Itertools;
+use crate::{AppError, Todo, sort_todo_list};
+use crate::journal::Journal;
+use crate::config::Config;
 
 fn get_confdir() -> Result<PathBuf, Box<dyn Error>> {
     let confdir = config().map(|path| path.join("tort_todo"))
@@ -15,11 +19,33 @@ fn get_confdir() -> Result<PathBuf, Box<dyn Error>> {
     Err(Box::new(AppError::NoConfigurationDirectory))
 }
 
+const IGNORED_SIDECARS: [&str; 2] = ["todolists/.*.lock", "todolists/.*.tmp"];
+
+/// Adds the lock and temporary files to the repo's .gitignore, keeping
+/// whatever else it lists. Runs when the repo is set up and when its older
+/// settings are migrated, so config dirs made by older versions get them too.
+fn ignore_sidecars(repo: &Path) -> Result<(), Box<dyn Error>> {
+    let path = repo.join(".gitignore");
+    let mut contents = read_to_string(&path).unwrap_or_default();
+    let missing: Vec<&str> = IGNORED_SIDECARS.into_iter()
+        .filter(|entry| !contents.lines().any(|line| line.trim() == *entry))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    missing.iter().for_each(|entry| contents.push_str(&format!("{}\n", entry)));
+    write_atomic(&path, &contents)
+}
+
 fn create_confdir() -> Result<PathBuf, Box<dyn Error>> {
     let confdir = config().map(|path| path.join("tort_todo"))
                           .unwrap_or_default();
     create_dir(&confdir)?;
-    create_dir(&confdir.join("todolists"))?;
+    create_dir(confdir.join("todolists"))?;
+    ignore_sidecars(&confdir)?;
     let _out = Command::new("git")
         .args(["-C", confdir.to_str().unwrap(), "init"])
         .output()?;
@@ -37,10 +63,226 @@ pub fn run_git_commands(a: &Vec<String>) -> Result<String, Box<dyn Error>> {
     Ok(String::from_utf8(out.stdout)?)
 }
 
+fn git_in(repo: &Path, args: &[&str]) -> Result<std::process::Output, Box<dyn Error>> {
+    Ok(Command::new("git")
+        .args(["-C", repo.to_str().unwrap()])
+        .args(args)
+        .output()?)
+}
+
+/// The config dir a todolist lives in, found from the todolist's own path.
+fn repo_of(todolist: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let todolist = canonicalize(todolist)?;
+    Ok(todolist.parent().and_then(|p| p.parent()).map(|p| p.to_path_buf()).unwrap_or_default())
+}
+
+/// Whether changes to the named todolist are committed to the config repo.
+pub fn auto_commit_enabled(name: &str) -> Result<bool, Box<dyn Error>> {
+    Ok(get_config()?.settings(Some(name)).auto_commit)
+}
+
+pub fn set_auto_commit(name: &str, enabled: bool) -> Result<(), Box<dyn Error>> {
+    let mut config = get_config()?;
+    config.set("auto_commit", if enabled { "true" } else { "false" }, Some(name))?;
+    config.save()
+}
+
+/// The URL the named todolist syncs with.
+pub fn sync_endpoint(name: &str) -> Result<Option<String>, Box<dyn Error>> {
+    Ok(get_config()?.settings(Some(name)).sync_endpoint)
+}
+
+pub fn set_sync_endpoint(name: &str, endpoint: &str) -> Result<(), Box<dyn Error>> {
+    let mut config = get_config()?;
+    config.set("sync_endpoint", endpoint, Some(name))?;
+    config.save()
+}
+
+/// `config.toml` in the config dir; empty until something is set.
+pub fn get_config() -> Result<Config, Box<dyn Error>> {
+    config_in(&config().map(|path| path.join("tort_todo")).unwrap_or_default())
+}
+
+/// `config.toml` as it is, even with bad values in it.
+pub fn get_config_unchecked() -> Result<Config, Box<dyn Error>> {
+    Config::load_unchecked(&config().map(|path| path.join("tort_todo")).unwrap_or_default())
+}
+
+fn config_in(repo: &Path) -> Result<Config, Box<dyn Error>> {
+    let mut config = Config::load(repo)?;
+    migrate_git_config(repo, &mut config)?;
+    Ok(config)
+}
+
+/// Moves the auto-commit and sync endpoint settings that older versions
+/// kept in the repo's git config into config.toml, keeping any value
+/// config.toml already has, and adds the lock and temporary files to the
+/// repo's .gitignore.
+fn migrate_git_config(repo: &Path, config: &mut Config) -> Result<(), Box<dyn Error>> {
+    if !repo.join(".git").exists() {
+        return Ok(());
+    }
+    ignore_sidecars(repo)?;
+    let out = git_in(repo, &["config", "--local", "--get-regexp", r"^tlist\..*\.(autocommit|syncendpoint)$"])?;
+    let listed = String::from_utf8(out.stdout)?;
+    let mut sections = Vec::new();
+    for line in listed.lines() {
+        let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+        let Some((list, old_key)) = name.strip_prefix("tlist.").and_then(|n| n.rsplit_once('.')) else { continue };
+        let (key, value) = match old_key {
+            "autocommit" => ("auto_commit", if ["true", "yes", "on", "1"].contains(&value) { "true" } else { "false" }),
+            _ => ("sync_endpoint", value),
+        };
+        if !config.is_set(key, list) {
+            config.set(key, value, Some(list))?;
+        }
+        sections.push(format!("tlist.{}", list));
+    }
+    if sections.is_empty() {
+        return Ok(());
+    }
+    config.save()?;
+    for section in sections.iter().unique() {
+        git_in(repo, &["config", "--local", "--remove-section", section])?;
+    }
+    Ok(())
+}
+
+/// Commits `paths` in the config repo, leaving anything else in it alone
+/// and doing nothing if they are unchanged.
+fn commit_changes(repo: &Path, paths: &[&Path], message: &str) -> Result<(), Box<dyn Error>> {
+    let paths: Vec<&str> = paths.iter()
+        .map(|p| p.strip_prefix(repo).unwrap_or(p).to_str().unwrap())
+        .collect();
+    git_in(repo, &[&["add", "--"], &paths[..]].concat())?;
+    let status = git_in(repo, &[&["status", "--porcelain", "--"], &paths[..]].concat())?;
+    if status.stdout.is_empty() {
+        return Ok(());
+    }
+    let has_identity = !git_in(repo, &["config", "user.email"])?.stdout.is_empty();
+    let identity: &[&str] = if has_identity { &[] } else { &["-c", "user.name=tlist", "-c", "user.email=tlist@localhost"] };
+    let out = git_in(repo, &[identity, &["commit", "-q", "-m", message, "--"], &paths[..]].concat())?;
+    if !out.status.success() {
+        return Err(Box::new(AppError::GitError(String::from_utf8(out.stderr)?.trim().to_string())));
+    }
+    Ok(())
+}
+
 pub fn get_active_todo() -> Result<PathBuf, Box<dyn Error>> {
     get_confdir().map(|v| v.join("active_todos.json"))
 }
 
+pub fn load_todos(path: &Path) -> Result<Vec<Todo>, Box<dyn Error>> {
+    let todos_raw = read_to_string(path)?;
+    Ok(serde_json::from_str(&todos_raw)?)
+}
+
+fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+/// Writes to a temporary file next to `path` and renames it into place, so
+/// a crash never leaves a truncated file behind.
+pub(crate) fn write_atomic(path: &Path, contents: &str) -> Result<(), Box<dyn Error>> {
+    let tmp_path = sidecar_path(path, "tmp");
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(contents.as_bytes())?;
+    tmp.sync_all()?;
+    rename(&tmp_path, path)?;
+    Ok(())
+}
+
+pub fn save_todos(path: &Path, todos: &mut Vec<Todo>) -> Result<(), Box<dyn Error>> {
+    sort_todo_list(todos);
+    write_atomic(&canonicalize(path)?, &serde_json::to_string(todos)?)
+}
+
+/// A todolist held under an exclusive advisory lock for a whole
+/// read-modify-write cycle.
+pub struct LockedTodolist {
+    path: PathBuf,
+    contents: String,
+    _lock: File,
+}
+
+impl LockedTodolist {
+    pub fn open(path: &Path) -> Result<(LockedTodolist, Vec<Todo>), Box<dyn Error>> {
+        let path = canonicalize(path)?;
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(sidecar_path(&path, "lock"))?;
+        lock.lock()?;
+        let contents = read_to_string(&path)?;
+        let todos: Vec<Todo> = serde_json::from_str(&contents)?;
+        Ok((LockedTodolist { path, contents, _lock: lock }, todos))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Fails with `TodolistChangedError` instead of overwriting changes made
+    /// by a writer that ignored the lock.
+    pub fn save(&mut self, todos: &mut Vec<Todo>) -> Result<(), Box<dyn Error>> {
+        if read_to_string(&self.path)? != self.contents {
+            return Err(Box::new(AppError::TodolistChangedError(self.path.display().to_string())));
+        }
+        save_todos(&self.path, todos)?;
+        self.contents = read_to_string(&self.path)?;
+        Ok(())
+    }
+}
+
+pub fn todolist_name(todolist: &Path) -> Result<String, Box<dyn Error>> {
+    let mut todolist = canonicalize(todolist)?;
+    todolist.set_extension("");
+    Ok(todolist.file_name().unwrap_or_default().to_string_lossy().to_string())
+}
+
+/// The undo journal for a todolist, kept in the config dir so it is
+/// independent of the git history.
+pub fn get_journal(todolist: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let name = canonicalize(todolist)?.file_name().unwrap_or_default().to_os_string();
+    Ok(repo_of(todolist)?.join("journals").join(name))
+}
+
+/// The todolist as it was after the last sync, the base of the next
+/// three-way merge.
+pub fn get_sync_snapshot(todolist: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let name = canonicalize(todolist)?.file_name().unwrap_or_default().to_os_string();
+    Ok(repo_of(todolist)?.join("sync").join(name))
+}
+
+pub fn get_todolist(name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    get_confdir().map(|v| v.join("todolists").join(format!("{}.json", name)))
+}
+
+/// The names of all todolists, each paired with whether it is the active one.
+pub fn todolists() -> Result<Vec<(String, bool)>, Box<dyn Error>> {
+    todolists_in(&get_confdir()?)
+}
+
+pub(crate) fn todolists_in(confdir: &Path) -> Result<Vec<(String, bool)>, Box<dyn Error>> {
+    let link = read_link(confdir.join("active_todos.json")).ok();
+    let active_file = link.as_ref().and_then(|f| f.file_name());
+
+    let mut names = Vec::new();
+    for file in read_dir(confdir.join("todolists"))? {
+        let name = file?.file_name();
+        if name.to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let mut name_without_extension = PathBuf::from(&name);
+        name_without_extension.set_extension("");
+        names.push((name_without_extension.display().to_string(), Some(name.as_os_str()) == active_file));
+    }
+    names.sort();
+    Ok(names)
+}
+
 pub fn init_todo(name: &str) -> Result<(), Box<dyn Error>> {
     let confdir = get_confdir().or(create_confdir())?;
 
@@ -69,29 +311,18 @@ pub fn set_active(name: &str) -> Result<(), Box<dyn Error>> {
 }
 
 pub fn list_todos() -> Result<(), Box<dyn Error>>{
-    let confdir = get_confdir().or(create_confdir())?;
-    let mut contents = read_dir(confdir.join("todolists"))?.peekable();
-    if contents.peek().is_none() {
+    get_confdir().or(create_confdir())?;
+    let names = todolists()?;
+    if names.is_empty() {
         println!("no todos yet!");
         return Ok(());
     }
 
-    let link = read_link(confdir.join("active_todos.json")).ok();
-    let active_file = match link {
-        Some(f) => f.file_name()
-                    .and_then(|x| x.to_str()
-                                   .map(|v| v.to_string())),
-        None => None
-    };
-
-    for file in contents {
-        let name = file?.file_name();
-        let mut name_without_extension = PathBuf::from(&name);
-        name_without_extension.set_extension("");
-        if name.to_str() == active_file.as_deref() {
-            println!("{} (active)", name_without_extension.display()); 
+    for (name, active) in names {
+        if active {
+            println!("{} (active)", name); 
         } else {
-            println!("{}", name_without_extension.display());
+            println!("{}", name);
         }
     }
     Ok(())
@@ -99,7 +330,11 @@ pub fn list_todos() -> Result<(), Box<dyn Error>>{
 
 pub fn delete_todolist(name: &str) -> Result<(), Box<dyn Error>> {
     let confdir = get_confdir()?;
-    remove_file(confdir.join("todolists").join(format!("{}.json", name)))?;
+    let todolist = confdir.join("todolists").join(format!("{}.json", name));
+    remove_file(&todolist)?;
+    let _ = remove_file(sidecar_path(&todolist, "lock"));
+    let _ = remove_file(confdir.join("journals").join(format!("{}.json", name)));
+    let _ = remove_file(confdir.join("sync").join(format!("{}.json", name)));
     let mut link = read_link(confdir.join("active_todos.json"))?;
     link.set_extension("");
     let active_file = link.file_name()
@@ -115,4 +350,164 @@ pub fn nuke_all_todolists() -> Result<(), Box<dyn Error>> {
     remove_dir_all(&confdir)?;
     println!("{} has been nuked. Kaboom.", confdir.display());
     Ok(())
-}
\ No newline at end of file
+}
+
+/// A locked todolist together with its journal, shared by every frontend so
+/// that each change is saved, journaled and committed the same way.
+pub struct Transaction {
+    locked: LockedTodolist,
+    pub todos: Vec<Todo>,
+    pub journal: Journal,
+    journal_file: PathBuf,
+    before: Vec<Todo>,
+}
+
+impl Transaction {
+    pub fn open(path: &Path) -> Result<Transaction, Box<dyn Error>> {
+        let (locked, todos) = LockedTodolist::open(path)?;
+        let journal_file = get_journal(locked.path())?;
+        let journal = Journal::load(&journal_file)?;
+        let before = todos.clone();
+        Ok(Transaction { locked, todos, journal, journal_file, before })
+    }
+
+    pub fn path(&self) -> &Path {
+        self.locked.path()
+    }
+
+    /// Saves the list, recording `operation` in the journal and committing
+    /// it when auto-commit is on.
+    pub fn commit(mut self, operation: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if let Some(op) = operation {
+            self.journal.record(op.to_string(), &self.before, &self.todos);
+        }
+        self.commit_unrecorded(operation)
+    }
+
+    /// Like `commit`, but for changes that must not become journal entries
+    /// themselves, such as undo and redo.
+    pub fn commit_unrecorded(mut self, operation: Option<&str>) -> Result<(), Box<dyn Error>> {
+        self.locked.save(&mut self.todos)?;
+        let Some(op) = operation else { return Ok(()) };
+        self.journal.save(&self.journal_file)?;
+        let repo = repo_of(self.locked.path())?;
+        if config_in(&repo)?.settings(Some(&todolist_name(self.locked.path())?)).auto_commit {
+            commit_changes(&repo, &[&canonicalize(self.locked.path())?, &self.journal_file], op)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::create_dir_all;
+    use uuid::Uuid;
+
+    fn gen_todolist() -> PathBuf {
+        let dir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4()));
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("list.json");
+        write(&path, "[]").unwrap();
+        path
+    }
+
+    #[test]
+    fn test_ignore_sidecars() {
+        let repo = gen_todolist().parent().unwrap().to_path_buf();
+        write(repo.join(".gitignore"), "notes.txt\ntodolists/.*.lock").unwrap();
+
+        assert!(ignore_sidecars(&repo).is_ok());
+        assert!(ignore_sidecars(&repo).is_ok());
+
+        assert_eq!(read_to_string(repo.join(".gitignore")).unwrap(), "notes.txt\ntodolists/.*.lock\ntodolists/.*.tmp\n");
+        remove_dir_all(repo).unwrap();
+    }
+
+    #[test]
+    fn test_locked_save() {
+        let path = gen_todolist();
+        let (mut locked, mut todos) = LockedTodolist::open(&path).unwrap();
+        todos.push(Todo::builder("subject").build());
+
+        let r = locked.save(&mut todos);
+
+        assert!(r.is_ok());
+        assert_eq!(load_todos(&path).unwrap(), todos);
+        assert!(!sidecar_path(&canonicalize(&path).unwrap(), "tmp").exists());
+        remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_locked_save_changed_underneath() {
+        let path = gen_todolist();
+        let (mut locked, mut todos) = LockedTodolist::open(&path).unwrap();
+        write(&path, "[ ]").unwrap();
+
+        let r = locked.save(&mut todos);
+
+        assert!(r.is_err());
+        assert_eq!(read_to_string(&path).unwrap(), "[ ]");
+        remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_save_through_symlink() {
+        let path = gen_todolist();
+        let link = path.with_file_name("active.json");
+        symlink(&path, &link).unwrap();
+        let (mut locked, mut todos) = LockedTodolist::open(&link).unwrap();
+        todos.push(Todo::builder("subject").build());
+
+        assert!(locked.save(&mut todos).is_ok());
+
+        assert!(read_link(&link).is_ok());
+        assert_eq!(load_todos(&path).unwrap().len(), 1);
+        remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_commit_changes_only_given_paths() {
+        let path = gen_todolist();
+        let repo = path.parent().unwrap();
+        if git_in(repo, &["init", "-q"]).is_err() {
+            return;
+        }
+        let other = repo.join("notes.txt");
+        write(&other, "unrelated").unwrap();
+        write(&path, "[ ]").unwrap();
+
+        assert!(commit_changes(repo, &[&path], "add todo").is_ok());
+
+        let committed = git_in(repo, &["show", "--name-only", "--format=", "HEAD"]).unwrap();
+        assert_eq!(String::from_utf8(committed.stdout).unwrap().trim(), "list.json");
+        let status = git_in(repo, &["status", "--porcelain"]).unwrap();
+        assert_eq!(String::from_utf8(status.stdout).unwrap().trim(), "?? notes.txt");
+        assert!(commit_changes(repo, &[&path], "nothing").is_ok());
+        remove_dir_all(repo).unwrap();
+    }
+
+    #[test]
+    fn test_migrate_git_config() {
+        let path = gen_todolist();
+        let repo = path.parent().unwrap();
+        if git_in(repo, &["init", "-q"]).is_err() {
+            return;
+        }
+        git_in(repo, &["config", "--bool", "tlist.work.autocommit", "true"]).unwrap();
+        git_in(repo, &["config", "tlist.work.syncendpoint", "http://localhost:8080/todos"]).unwrap();
+        git_in(repo, &["config", "tlist.home.syncendpoint", "http://old.example/todos"]).unwrap();
+        write(repo.join("config.toml"), "[todolists.home]\nsync_endpoint = \"http://new.example/todos\"\n").unwrap();
+
+        let config = config_in(repo).unwrap();
+
+        assert!(config.settings(Some("work")).auto_commit);
+        assert_eq!(config.settings(Some("work")).sync_endpoint.as_deref(), Some("http://localhost:8080/todos"));
+        assert_eq!(config.settings(Some("home")).sync_endpoint.as_deref(), Some("http://new.example/todos"));
+        assert_eq!(Config::load(repo).unwrap(), config);
+        assert!(git_in(repo, &["config", "--get-regexp", "^tlist"]).unwrap().stdout.is_empty());
+        assert_eq!(read_to_string(repo.join(".gitignore")).unwrap(), "todolists/.*.lock\ntodolists/.*.tmp\n");
+        remove_dir_all(repo).unwrap();
+    }
+}
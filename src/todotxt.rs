@@ -0,0 +1,233 @@
+use chrono::{Local, NaiveDate, NaiveTime};
+use itertools::Itertools;
+use crate::{Todo, AppError, SerdeDate, SerdeDateTime, config};
+use crate::recur::Recurrence;
+use crate::modify::find_new_id;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+/// todo.txt has no times, so a due time goes in a `due_time:` tag named
+/// like the field in the todolist file.
+const TIME_FORMAT: &str = "%H:%M";
+
+fn recur_to_todotxt(recur: &str) -> Result<String, AppError> {
+    Ok(match Recurrence::try_from(recur)? {
+        Recurrence::Daily => "1d".to_string(),
+        Recurrence::Weekdays => "1b".to_string(),
+        Recurrence::Weekly => "1w".to_string(),
+        Recurrence::Monthly => "1m".to_string(),
+        Recurrence::Yearly => "1y".to_string(),
+        Recurrence::EveryNDays(n) => format!("{}d", n),
+        Recurrence::OnWeekdays(_) => recur.trim_start_matches("every ").to_string(),
+    })
+}
+
+fn recur_from_todotxt(rec: &str) -> Result<String, AppError> {
+    let rec = rec.trim_start_matches('+');
+    let invalid = || AppError::InvalidRecurrenceError(rec.to_string());
+    let Some(unit) = rec.chars().last() else { return Err(invalid()) };
+    let count = rec.strip_suffix(unit).unwrap_or_default();
+    let rule = match (count.parse::<u64>(), unit) {
+        (Ok(1), 'b') => Recurrence::Weekdays,
+        (Ok(1), 'w') => Recurrence::Weekly,
+        (Ok(1), 'm') => Recurrence::Monthly,
+        (Ok(1), 'y') => Recurrence::Yearly,
+        (Ok(n), 'd') if n > 0 => Recurrence::try_from(format!("every {} days", n).as_str())?,
+        (Ok(n), 'w') if n > 0 => Recurrence::try_from(format!("every {} days", n.checked_mul(7).ok_or_else(invalid)?).as_str())?,
+        (Err(_), _) => Recurrence::try_from(format!("every {}", rec).as_str())?,
+        _ => return Err(invalid()),
+    };
+    Ok(rule.to_string())
+}
+
+pub fn to_todotxt(todo: &Todo) -> Result<String, AppError> {
+    let mut words: Vec<String> = Vec::new();
+    let created = todo.created.date().map(|d| d.format(DATE_FORMAT).to_string());
+    if todo.completed {
+        words.push("x".to_string());
+        if let Some(date) = todo.completed_date.date() {
+            words.push(date.format(DATE_FORMAT).to_string());
+            // a creation date is only allowed after a completion date
+            words.extend(created);
+        }
+        if todo.is_priority {
+            words.push("pri:A".to_string());
+        }
+    } else {
+        if todo.is_priority {
+            words.push("(A)".to_string());
+        }
+        words.extend(created);
+    }
+    words.push(todo.subject.clone());
+    if let Some(due) = todo.due.date() {
+        words.push(format!("due:{}", due.format(DATE_FORMAT)));
+        if let Some(time) = todo.due.time() {
+            words.push(format!("due_time:{}", time.format(TIME_FORMAT)));
+        }
+    }
+    if !todo.recur.is_empty() {
+        words.push(format!("rec:{}", recur_to_todotxt(&todo.recur)?));
+    }
+    Ok(words.join(" "))
+}
+
+fn parse_date(word: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(word, DATE_FORMAT).ok()
+}
+
+fn is_priority(word: &str) -> bool {
+    let chars: Vec<char> = word.chars().collect();
+    matches!(chars.as_slice(), ['(', p, ')'] if p.is_ascii_uppercase())
+}
+
+fn start_of(date: Option<NaiveDate>) -> SerdeDateTime {
+    date.and_then(|d| d.and_time(NaiveTime::MIN).and_local_timezone(Local).single())
+        .map(SerdeDateTime::from)
+        .unwrap_or(SerdeDateTime::new_empty())
+}
+
+pub fn from_todotxt(line: &str) -> Result<Todo, AppError> {
+    let mut words = line.split_whitespace().peekable();
+    let completed = words.next_if_eq(&"x").is_some();
+    let completed_date = if completed { words.next_if(|w| parse_date(w).is_some()).and_then(parse_date) } else { None };
+    let mut priority = words.next_if(|w| is_priority(w)).is_some();
+    let created = words.next_if(|w| parse_date(w).is_some()).and_then(parse_date);
+
+    let mut due = SerdeDate::new_empty();
+    let mut due_time = None;
+    let mut recur = String::new();
+    let mut subject: Vec<&str> = Vec::new();
+    for word in words {
+        match word.split_once(':') {
+            Some(("due", d)) if parse_date(d).is_some() => due = parse_date(d).map(SerdeDate::from).unwrap(),
+            Some(("due_time", t)) if NaiveTime::parse_from_str(t, TIME_FORMAT).is_ok() => due_time = NaiveTime::parse_from_str(t, TIME_FORMAT).ok(),
+            Some(("rec", r)) => recur = recur_from_todotxt(r)?,
+            Some(("pri", _)) => priority = true,
+            _ => subject.push(word),
+        }
+    }
+
+    let due = if due.is_some() { due.with_time(due_time) } else { due };
+    let mut todo = Todo::builder(&subject.join(" "))
+        .due(due)
+        .priority(priority)
+        .recur(&recur)?
+        .created(start_of(created))
+        .build();
+    if completed {
+        todo.completed = true;
+        todo.status = config::current().completed_status.clone();
+        todo.completed_date = start_of(completed_date);
+    }
+    Ok(todo)
+}
+
+pub fn export(todos: &Vec<Todo>) -> Result<String, AppError> {
+    Ok(todos.iter().map(to_todotxt).collect::<Result<Vec<String>, AppError>>()?.iter().map(|l| format!("{}\n", l)).join(""))
+}
+
+/// Adds every task in a todo.txt file to the list, returning how many were
+/// imported. Nothing is added if any line fails to parse.
+pub fn import(todos: &mut Vec<Todo>, text: &str) -> Result<usize, AppError> {
+    let imported = text.lines()
+                       .filter(|l| !l.trim().is_empty())
+                       .map(from_todotxt)
+                       .collect::<Result<Vec<Todo>, AppError>>()?;
+    let count = imported.len();
+    for mut todo in imported {
+        todo.id = find_new_id(todos);
+        todos.push(todo);
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GOLDEN_TODOTXT: &str = include_str!("../tests/golden/todo.txt");
+    const GOLDEN_JSON: &str = include_str!("../tests/golden/todos.json");
+
+    #[test]
+    fn test_export_golden() {
+        let todos: Vec<Todo> = serde_json::from_str(GOLDEN_JSON).unwrap();
+
+        assert_eq!(export(&todos), Ok(GOLDEN_TODOTXT.to_string()));
+    }
+
+    #[test]
+    fn test_import_golden() {
+        let expected: Vec<Todo> = serde_json::from_str(GOLDEN_JSON).unwrap();
+        let mut todos = Vec::new();
+
+        assert_eq!(import(&mut todos, GOLDEN_TODOTXT), Ok(expected.len()));
+
+        for (todo, expected) in todos.iter().zip(expected.iter()) {
+            assert_eq!(todo.id, expected.id);
+            assert_eq!(todo.subject, expected.subject);
+            assert_eq!(todo.projects, expected.projects);
+            assert_eq!(todo.contexts, expected.contexts);
+            assert_eq!(todo.due, expected.due);
+            assert_eq!(todo.completed, expected.completed);
+            assert_eq!(todo.completed_date.date().map(|d| d.date_naive()), expected.completed_date.date().map(|d| d.date_naive()));
+            assert_eq!(todo.is_priority, expected.is_priority);
+            assert_eq!(todo.recur, expected.recur);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut todos = Vec::new();
+        assert!(import(&mut todos, GOLDEN_TODOTXT).is_ok());
+
+        assert_eq!(export(&todos), Ok(GOLDEN_TODOTXT.to_string()));
+    }
+
+    #[test]
+    fn test_import_foreign_fields() {
+        let todo = from_todotxt("(B) 2025-01-01 Call mom @phone t:2025-01-02 rec:+2d").unwrap();
+
+        assert!(todo.is_priority);
+        assert!(!todo.completed);
+        assert_eq!(todo.subject, "Call mom @phone t:2025-01-02");
+        assert_eq!(todo.contexts, vec!["phone"]);
+        assert_eq!(todo.recur, "every 2 days");
+        assert_eq!(todo.created.date().map(|d| d.date_naive()), NaiveDate::from_ymd_opt(2025, 1, 1));
+    }
+
+    #[test]
+    fn test_creation_date() {
+        for line in ["(A) 2025-01-01 Call mom", "2025-01-01 Call mom", "x 2025-01-03 2025-01-01 Call mom"] {
+            assert_eq!(to_todotxt(&from_todotxt(line).unwrap()), Ok(line.to_string()));
+        }
+        let mut todo = from_todotxt("x 2025-01-03 2025-01-01 Call mom").unwrap();
+        todo.completed_date = SerdeDateTime::new_empty();
+        assert_eq!(to_todotxt(&todo), Ok("x Call mom".to_string()));
+    }
+
+    #[test]
+    fn test_due_time() {
+        let line = "Call mom due:2025-01-15 due_time:15:30";
+        let todo = from_todotxt(line).unwrap();
+
+        assert_eq!(todo.due.time(), NaiveTime::from_hms_opt(15, 30, 0));
+        assert_eq!(to_todotxt(&todo), Ok(line.to_string()));
+        assert_eq!(from_todotxt("Call mom due_time:15:30").unwrap().due, SerdeDate::new_empty());
+        assert_eq!(from_todotxt("Call mom due:2025-01-15 due_time:soon").unwrap().subject, "Call mom due_time:soon");
+    }
+
+    #[test]
+    fn test_import_invalid_recurrence() {
+        let mut todos = Vec::new();
+
+        let r = import(&mut todos, "first\nsecond rec:3m\n");
+
+        assert_eq!(r, Err(AppError::InvalidRecurrenceError("3m".to_string())));
+        assert!(todos.is_empty());
+
+        assert!(import(&mut todos, "first rec:2é\n").is_err());
+        assert!(import(&mut todos, "first rec:é\n").is_err());
+        assert!(import(&mut todos, "first rec:+\n").is_err());
+        assert!(import(&mut todos, "first rec:3000000000000000000w\n").is_err());
+    }
+}
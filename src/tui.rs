@@ -0,0 +1,577 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use ratatui::{DefaultTerminal, Frame};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph};
+use crate::{Todo, AppError, Column, GroupOption, SerdeDate, config, theme};
+use crate::filter::Filter;
+use crate::list::todo_grouping;
+use crate::modify::{add, edit, status, complete, prioritize, describe_todo, find_todo_mut};
+use crate::notes::{add_note, edit_note, delete_note};
+use crate::todo_files::{load_todos, todolists, get_todolist, todolist_name, Transaction};
+
+const HELP: &str = "j/k move  c complete  p priority  e subject  d due  s status  a add  n note  tab notes  g group  / filter  t todolists  u/r undo/redo  q quit";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Add,
+    Subject(u64),
+    Due(u64),
+    Status(u64),
+    AddNote(u64),
+    EditNote(u64, usize),
+}
+
+impl Field {
+    fn label(&self) -> &str {
+        match self {
+            Field::Add => "New todo",
+            Field::Subject(_) => "Subject",
+            Field::Due(_) => "Due",
+            Field::Status(_) => "Status",
+            Field::AddNote(_) => "New note",
+            Field::EditNote(_, _) => "Note",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Mode {
+    Normal,
+    Notes(usize),
+    Input(Field, String),
+    Filter,
+    Todolists(usize, Vec<String>),
+}
+
+/// The state of `tlist tui`. Key handling is kept apart from drawing so it
+/// can run without a terminal; every change goes through a `Transaction`,
+/// exactly like the CLI commands.
+pub struct App {
+    path: PathBuf,
+    todos: Vec<Todo>,
+    grouping: Option<GroupOption>,
+    filter_text: String,
+    filter: Filter,
+    selected: usize,
+    mode: Mode,
+    message: String,
+    quit: bool,
+}
+
+fn next_grouping(grouping: Option<GroupOption>) -> Option<GroupOption> {
+    match grouping {
+        None => Some(GroupOption::Project),
+        Some(GroupOption::Project) => Some(GroupOption::Context),
+        Some(GroupOption::Context) => Some(GroupOption::Status),
+        Some(GroupOption::Status) => None,
+    }
+}
+
+fn step(index: usize, delta: isize, len: usize) -> usize {
+    index.saturating_add_signed(delta).min(len.saturating_sub(1))
+}
+
+impl App {
+    pub fn open(path: &Path) -> Result<App, Box<dyn Error>> {
+        Ok(App {
+            path: path.to_path_buf(),
+            todos: load_todos(path)?,
+            grouping: config::current().group,
+            filter_text: String::new(),
+            filter: Filter::parse(&[])?,
+            selected: 0,
+            mode: Mode::Normal,
+            message: String::new(),
+            quit: false,
+        })
+    }
+
+    /// The visible groups, sorted by title, with the ids of their todos.
+    fn groups(&self) -> Vec<(String, Vec<u64>)> {
+        let visible: Vec<Todo> = self.todos.iter().filter(|t| self.filter.matches(t)).cloned().collect();
+        let mut groups: Vec<(String, Vec<u64>)> = todo_grouping(&visible, self.grouping)
+            .into_iter()
+            .map(|(title, todos)| (title.to_string(), todos.iter().map(|t| t.id).collect()))
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    /// Every selectable row; a todo in several groups appears once per group.
+    fn rows(&self) -> Vec<u64> {
+        self.groups().into_iter().flat_map(|(_, ids)| ids).collect()
+    }
+
+    fn selected_todo(&self) -> Option<&Todo> {
+        let id = *self.rows().get(self.selected)?;
+        self.todos.iter().find(|t| t.id == id)
+    }
+
+    /// Re-reads the list, keeping the same todo selected if it is still shown.
+    fn reload(&mut self) -> Result<(), Box<dyn Error>> {
+        let selected = self.selected_todo().map(|t| t.id);
+        self.todos = load_todos(&self.path)?;
+        let rows = self.rows();
+        self.selected = match rows.iter().position(|id| Some(*id) == selected) {
+            Some(i) => i,
+            None => self.selected.min(rows.len().saturating_sub(1)),
+        };
+        Ok(())
+    }
+
+    fn apply(&mut self, operation: String, f: impl FnOnce(&mut Vec<Todo>) -> Result<(), AppError>) -> Result<(), Box<dyn Error>> {
+        let mut tx = Transaction::open(&self.path)?;
+        f(&mut tx.todos)?;
+        tx.commit(Some(&operation))?;
+        self.reload()
+    }
+
+    fn undo(&mut self, redo: bool) -> Result<(), Box<dyn Error>> {
+        let mut tx = Transaction::open(&self.path)?;
+        let done = if redo { tx.journal.redo(&mut tx.todos, 1)? } else { tx.journal.undo(&mut tx.todos, 1)? };
+        let (verb, past) = if redo { ("redo", "Redid") } else { ("undo", "Undid") };
+        let operation = done.first().map(|op| format!("{} {}", verb, op));
+        tx.commit_unrecorded(operation.as_deref())?;
+        self.message = match done.first() {
+            Some(op) => format!("{} {}", past, op),
+            None => format!("Nothing to {}.", verb),
+        };
+        self.reload()
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        self.message.clear();
+        let r = match self.mode.clone() {
+            Mode::Normal => self.normal_key(key),
+            Mode::Notes(i) => self.notes_key(i, key),
+            Mode::Input(_, _) => self.input_key(key),
+            Mode::Filter => self.filter_key(key),
+            Mode::Todolists(i, names) => self.todolists_key(i, names, key),
+        };
+        if let Err(e) = r {
+            self.message = e.to_string();
+        }
+    }
+
+    fn normal_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn Error>> {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Char('j') | KeyCode::Down => self.selected = step(self.selected, 1, self.rows().len()),
+            KeyCode::Char('k') | KeyCode::Up => self.selected = step(self.selected, -1, self.rows().len()),
+            KeyCode::Char('g') => {
+                self.grouping = next_grouping(self.grouping);
+                self.selected = 0;
+            },
+            KeyCode::Char('/') => self.mode = Mode::Filter,
+            KeyCode::Char('a') => self.mode = Mode::Input(Field::Add, String::new()),
+            KeyCode::Char('t') => {
+                let names = todolists()?.into_iter().map(|(name, _)| name).collect();
+                self.mode = Mode::Todolists(0, names);
+            },
+            KeyCode::Char('u') => self.undo(false)?,
+            KeyCode::Char('r') => self.undo(true)?,
+            _ => {
+                let Some(todo) = self.selected_todo().cloned() else { return Ok(()) };
+                let id = todo.id;
+                let described = describe_todo(&self.todos, id);
+                match key.code {
+                    KeyCode::Char('c') | KeyCode::Char(' ') => {
+                        let set = !todo.completed;
+                        let verb = if set { "complete" } else { "uncomplete" };
+                        self.apply(format!("{} {}", verb, described), |t| complete(t, id, set).map(|_| ()))?;
+                    },
+                    KeyCode::Char('p') => {
+                        let set = !todo.is_priority;
+                        let verb = if set { "prioritize" } else { "unprioritize" };
+                        self.apply(format!("{} {}", verb, described), |t| prioritize(t, id, set))?;
+                    },
+                    KeyCode::Char('e') => self.mode = Mode::Input(Field::Subject(id), todo.subject.clone()),
+                    KeyCode::Char('d') => {
+                        let due = todo.due.format_iso();
+                        self.mode = Mode::Input(Field::Due(id), due);
+                    },
+                    KeyCode::Char('s') => self.mode = Mode::Input(Field::Status(id), todo.status.clone()),
+                    KeyCode::Char('n') => self.mode = Mode::Input(Field::AddNote(id), String::new()),
+                    KeyCode::Tab if !todo.notes().is_empty() => self.mode = Mode::Notes(0),
+                    _ => {},
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn notes_key(&mut self, index: usize, key: KeyEvent) -> Result<(), Box<dyn Error>> {
+        let Some(todo) = self.selected_todo().cloned() else {
+            self.mode = Mode::Normal;
+            return Ok(());
+        };
+        let id = todo.id;
+        let notes = todo.notes();
+        match key.code {
+            KeyCode::Tab | KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Char('j') | KeyCode::Down => self.mode = Mode::Notes(step(index, 1, notes.len())),
+            KeyCode::Char('k') | KeyCode::Up => self.mode = Mode::Notes(step(index, -1, notes.len())),
+            KeyCode::Char('e') => if let Some(note) = notes.get(index) {
+                self.mode = Mode::Input(Field::EditNote(id, index), note.clone());
+            },
+            KeyCode::Char('n') => self.mode = Mode::Input(Field::AddNote(id), String::new()),
+            KeyCode::Char('x') => {
+                let operation = format!("delete note {} on {}", index, describe_todo(&self.todos, id));
+                self.apply(operation, |t| delete_note(t, id, index))?;
+                self.mode = if notes.len() > 1 { Mode::Notes(index.min(notes.len() - 2)) } else { Mode::Normal };
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn input_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn Error>> {
+        let Mode::Input(field, text) = &mut self.mode else { return Ok(()) };
+        match key.code {
+            KeyCode::Char(c) => text.push(c),
+            KeyCode::Backspace => { text.pop(); },
+            KeyCode::Esc => self.mode = Mode::Normal,
+            KeyCode::Enter => {
+                let (field, text) = (field.clone(), text.clone());
+                self.mode = Mode::Normal;
+                self.submit(field, text)?;
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    fn submit(&mut self, field: Field, text: String) -> Result<(), Box<dyn Error>> {
+        let described = |id| describe_todo(&self.todos, id);
+        match field {
+            Field::Add => {
+                let mut new_id = 0;
+                self.apply(format!("add: {}", text), |t| {
+                    new_id = add(t, text, SerdeDate::new_empty(), None, SerdeDate::new_empty())?;
+                    Ok(())
+                })?;
+                self.message = format!("Todo {} added.", new_id);
+            },
+            Field::Subject(id) => {
+                let operation = format!("edit {}", described(id));
+                self.apply(operation, |t| edit(t, id, text, None, None, None))?;
+            },
+            Field::Due(id) => {
+                let due = SerdeDate::try_from(Some(text))?;
+                let operation = format!("edit {}", described(id));
+                self.apply(operation, |t| {
+                    let subject = find_todo_mut(t, id)?.subject.clone();
+                    edit(t, id, subject, Some(due), None, None)
+                })?;
+            },
+            Field::Status(id) => {
+                let operation = format!("set status \"{}\" on {}", text, described(id));
+                self.apply(operation, |t| status(t, id, text))?;
+            },
+            Field::AddNote(id) => {
+                let operation = format!("add note to {}", described(id));
+                self.apply(operation, |t| add_note(t, id, text))?;
+            },
+            Field::EditNote(id, index) => {
+                let operation = format!("edit note {} on {}", index, described(id));
+                self.apply(operation, |t| edit_note(t, id, index, text))?;
+                self.mode = Mode::Notes(index);
+            },
+        }
+        Ok(())
+    }
+
+    fn filter_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn Error>> {
+        match key.code {
+            KeyCode::Char(c) => self.filter_text.push(c),
+            KeyCode::Backspace => { self.filter_text.pop(); },
+            KeyCode::Enter => self.mode = Mode::Normal,
+            KeyCode::Esc => {
+                self.filter_text.clear();
+                self.mode = Mode::Normal;
+            },
+            _ => return Ok(()),
+        }
+        self.selected = 0;
+        // keep showing the last valid filter while a term is half typed
+        self.filter = Filter::parse(std::slice::from_ref(&self.filter_text))?;
+        Ok(())
+    }
+
+    fn todolists_key(&mut self, index: usize, names: Vec<String>, key: KeyEvent) -> Result<(), Box<dyn Error>> {
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => self.mode = Mode::Todolists(step(index, 1, names.len()), names),
+            KeyCode::Char('k') | KeyCode::Up => self.mode = Mode::Todolists(step(index, -1, names.len()), names),
+            KeyCode::Esc | KeyCode::Char('q') => self.mode = Mode::Normal,
+            KeyCode::Enter => {
+                self.mode = Mode::Normal;
+                let Some(name) = names.get(index) else { return Ok(()) };
+                self.path = get_todolist(name)?;
+                self.selected = 0;
+                self.reload()?;
+            },
+            _ => {},
+        }
+        Ok(())
+    }
+
+    /// A todo styled with the current theme, like a row of `tlist list`.
+    fn todo_line(todo: &Todo) -> Line<'_> {
+        let theme = theme::current();
+        let overdue = !todo.completed && todo.due.is_overdue();
+        let cell = |column: Column, text: String| Span::styled(text, theme.tui_cell(column, todo.is_priority, column == Column::Due && overdue));
+        let mut spans = vec![
+            cell(Column::Id, format!("{:>4} ", todo.id)),
+            cell(Column::Done, (if todo.completed { "[x] " } else { "[ ] " }).to_string()),
+            cell(Column::Due, format!("{:<10} ", todo.due.to_string())),
+            cell(Column::Status, format!("{:<12} ", todo.status)),
+        ];
+        for word in todo.subject.split_whitespace() {
+            spans.push(Span::styled(word, theme.tui_word(word, todo.is_priority)));
+            spans.push(Span::raw(" "));
+        }
+        Line::from(spans)
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, footer] = Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, notes_area] = Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)]).areas(main);
+
+        let mut items = Vec::new();
+        let mut selected_item = None;
+        let mut row = 0;
+        for (title, ids) in self.groups() {
+            let title = Line::from(title).bold();
+            items.push(ListItem::new(if colored::control::SHOULD_COLORIZE.should_colorize() { title.cyan() } else { title }));
+            for id in ids {
+                if row == self.selected {
+                    selected_item = Some(items.len());
+                }
+                row += 1;
+                if let Some(todo) = self.todos.iter().find(|t| t.id == id) {
+                    items.push(ListItem::new(App::todo_line(todo)));
+                }
+            }
+        }
+        let name = todolist_name(&self.path).unwrap_or_default();
+        let grouping = match self.grouping {
+            None => "all",
+            Some(GroupOption::Project) => "by project",
+            Some(GroupOption::Context) => "by context",
+            Some(GroupOption::Status) => "by status",
+        };
+        let title = format!(" {} ({}) {} ", name, grouping, self.filter_text);
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::new().reversed());
+        let mut state = ListState::default().with_selected(selected_item);
+        frame.render_stateful_widget(list, list_area, &mut state);
+
+        let notes: Vec<ListItem> = self.selected_todo()
+            .map(|t| t.notes().iter().map(|n| ListItem::new(n.as_str())).collect())
+            .unwrap_or_default();
+        let notes_index = if let Mode::Notes(i) = self.mode { Some(i) } else { None };
+        let notes = List::new(notes)
+            .block(Block::default().borders(Borders::ALL).title(" Notes "))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(notes, notes_area, &mut ListState::default().with_selected(notes_index));
+
+        let status_line = match &self.mode {
+            Mode::Input(field, text) => format!("{}: {}_", field.label(), text),
+            Mode::Filter => format!("/{}_", self.filter_text),
+            _ if !self.message.is_empty() => self.message.clone(),
+            Mode::Notes(_) => "j/k move  e edit  n add  x delete  tab back".to_string(),
+            _ => HELP.to_string(),
+        };
+        frame.render_widget(Paragraph::new(status_line), footer);
+
+        if let Mode::Todolists(index, names) = &self.mode {
+            let area = frame.area();
+            let popup = Rect::new(area.width / 4, area.height / 4, area.width / 2, area.height / 2);
+            let list = List::new(names.iter().map(|n| ListItem::new(n.as_str())))
+                .block(Block::default().borders(Borders::ALL).title(" Todolists "))
+                .highlight_style(Style::new().reversed());
+            frame.render_widget(Clear, popup);
+            frame.render_stateful_widget(list, popup, &mut ListState::default().with_selected(Some(*index)));
+        }
+    }
+
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn Error>> {
+        while !self.quit {
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                self.handle_key(key);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn run(path: &Path) -> Result<(), Box<dyn Error>> {
+    let mut app = App::open(path)?;
+    let mut terminal = ratatui::init();
+    let r = app.event_loop(&mut terminal);
+    ratatui::restore();
+    r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::temp_dir;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use ratatui::crossterm::event::KeyModifiers;
+    use uuid::Uuid;
+    use crate::todo_files::save_todos;
+
+    fn gen_todo() -> Vec<Todo> {
+        vec![
+            Todo::builder("Buy milk +groceries @town").id(0).build(),
+            Todo::builder("Write report +work").id(1).notes(vec!["first draft".to_string()]).build(),
+            Todo::builder("Call mom @phone").id(2).build(),
+        ]
+    }
+
+    fn gen_app() -> App {
+        let dir = temp_dir().join(format!("tlist-test-{}", Uuid::new_v4())).join("todolists");
+        create_dir_all(&dir).unwrap();
+        let path = dir.join("list.json");
+        write(&path, "[]").unwrap();
+        // keep the tests from running git whatever the defaults become
+        write(dir.parent().unwrap().join("config.toml"), "auto_commit = false\n").unwrap();
+        save_todos(&path, &mut gen_todo()).unwrap();
+        App::open(&path).unwrap()
+    }
+
+    fn cleanup(app: App) {
+        remove_dir_all(app.path.parent().unwrap().parent().unwrap()).unwrap();
+    }
+
+    fn press(app: &mut App, keys: &str) {
+        keys.chars().for_each(|c| app.handle_key(KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE)));
+    }
+
+    fn select(app: &mut App, id: u64) {
+        app.selected = app.rows().iter().position(|i| *i == id).unwrap();
+    }
+
+    fn saved(app: &App, id: u64) -> Todo {
+        load_todos(&app.path).unwrap().into_iter().find(|t| t.id == id).unwrap()
+    }
+
+    fn press_code(app: &mut App, code: KeyCode) {
+        app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+    }
+
+    #[test]
+    fn test_complete_and_undo() {
+        let mut app = gen_app();
+
+        select(&mut app, 1);
+        press(&mut app, "c");
+
+        assert!(saved(&app, 1).completed);
+        assert_eq!(app.selected_todo().map(|t| t.id), Some(1));
+
+        press(&mut app, "u");
+
+        assert_eq!(app.message, "Undid complete #1: Write report +work");
+        assert!(!load_todos(&app.path).unwrap().iter().any(|t| t.completed));
+        cleanup(app);
+    }
+
+    #[test]
+    fn test_edit_subject() {
+        let mut app = gen_app();
+
+        select(&mut app, 0);
+        press(&mut app, "e");
+        (0..4).for_each(|_| press_code(&mut app, KeyCode::Backspace));
+        press(&mut app, "cereal");
+        press_code(&mut app, KeyCode::Enter);
+
+        assert_eq!(app.mode, Mode::Normal);
+        assert_eq!(saved(&app, 0).subject, "Buy milk +groceries @cereal");
+        cleanup(app);
+    }
+
+    #[test]
+    fn test_edit_invalid_due() {
+        let mut app = gen_app();
+
+        select(&mut app, 0);
+        press(&mut app, "dnot a date");
+        press_code(&mut app, KeyCode::Enter);
+
+        assert!(!app.message.is_empty());
+        assert!(!saved(&app, 0).due.is_some());
+        cleanup(app);
+    }
+
+    #[test]
+    fn test_notes() {
+        let mut app = gen_app();
+
+        select(&mut app, 1);
+        press_code(&mut app, KeyCode::Tab);
+        assert_eq!(app.mode, Mode::Notes(0));
+        press(&mut app, "x");
+
+        assert_eq!(app.mode, Mode::Normal);
+        assert!(saved(&app, 1).notes().is_empty());
+        cleanup(app);
+    }
+
+    #[test]
+    fn test_edit_missing_note() {
+        let mut app = gen_app();
+
+        select(&mut app, 1);
+        app.mode = Mode::Notes(3);
+        press(&mut app, "e");
+
+        assert_eq!(app.mode, Mode::Notes(3));
+        cleanup(app);
+    }
+
+    #[test]
+    fn test_filter_and_grouping() {
+        let mut app = gen_app();
+
+        press(&mut app, "/@");
+        assert_eq!(app.rows(), vec![0, 2]);
+        press(&mut app, "phone");
+        press_code(&mut app, KeyCode::Enter);
+        assert_eq!(app.rows(), vec![2]);
+
+        press(&mut app, "/");
+        press_code(&mut app, KeyCode::Esc);
+        press(&mut app, "g");
+
+        let titles: Vec<String> = app.groups().into_iter().map(|(title, _)| title).collect();
+        assert_eq!(titles, vec!["No projects", "groceries", "work"]);
+        cleanup(app);
+    }
+
+    #[test]
+    fn test_invalid_filter_keeps_last() {
+        let mut app = gen_app();
+        let mut todos = load_todos(&app.path).unwrap();
+        todos.iter_mut().filter(|t| t.id == 1).for_each(|t| t.subject = "Write report due friday +work".to_string());
+        save_todos(&app.path, &mut todos).unwrap();
+        app.reload().unwrap();
+
+        press(&mut app, "/report due:");
+
+        assert_eq!(app.rows(), vec![1]);
+        assert!(app.message.contains("due:"));
+        cleanup(app);
+    }
+}